@@ -0,0 +1,101 @@
+//! Time and allocations of parsing into owned `String` or borrowed `Literal`,
+//! and of unescaping the literals
+//!
+//! Run with `cargo bench --bench parse`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use misc_conf::{apache, ast::Directive, lexer::Literal, nginx};
+
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ROUNDS: u32 = 20;
+
+/// `run` returns how many items it made, which are dropped inside
+fn bench(name: &str, run: impl Fn() -> usize) {
+    // warm up
+    run();
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        run();
+    }
+    let elapsed = start.elapsed() / ROUNDS;
+    let allocs = (ALLOCS.load(Ordering::Relaxed) - allocs) / ROUNDS as usize;
+    let bytes = (BYTES.load(Ordering::Relaxed) - bytes) / ROUNDS as usize;
+    println!("{name:<16} {elapsed:>12.2?} {allocs:>10} allocs {bytes:>12} bytes");
+}
+
+/// The fixtures matching `pattern` which are `valid`, concatenated `times`
+fn corpus(pattern: &str, times: usize, valid: impl Fn(&[u8]) -> bool) -> Vec<u8> {
+    let mut one = vec![];
+    for path in glob::glob(pattern).unwrap().flatten() {
+        let data = std::fs::read(&path).unwrap();
+        if valid(&data) {
+            one.extend(data);
+            one.push(b'\n');
+        }
+    }
+    one.repeat(times)
+}
+
+fn main() {
+    let nginx = corpus("tests/nginx/*.conf", 100, |d| {
+        nginx::parse::<String>(d).is_ok()
+    });
+    println!("nginx, {} bytes", nginx.len());
+    bench("String", || nginx::parse::<String>(&nginx).unwrap().len());
+    bench("Literal", || nginx::parse::<Literal>(&nginx).unwrap().len());
+
+    let apache = corpus("tests/apache/*.conf", 100, |d| {
+        apache::parse::<String>(d).is_ok()
+    });
+    println!("apache, {} bytes", apache.len());
+    bench("String", || apache::parse::<String>(&apache).unwrap().len());
+    bench("Literal", || {
+        apache::parse::<Literal>(&apache).unwrap().len()
+    });
+
+    let root = apache::parse::<Literal>(&apache).unwrap();
+    let mut literals = vec![];
+    collect(&root, &mut literals);
+    println!("unescape, {} literals", literals.len());
+    bench("String::from", || {
+        literals.iter().map(|l| String::from(*l).len()).sum()
+    });
+    bench("unescape", || {
+        literals.iter().map(|l| l.unescape().len()).sum()
+    });
+}
+
+/// Names and arguments of `dirs` and their descendants
+fn collect<'a, S: Clone + Default>(dirs: &[Directive<S, Literal<'a>>], out: &mut Vec<Literal<'a>>) {
+    for d in dirs {
+        out.push(d.name);
+        out.extend(d.args.iter().copied());
+        collect(d.children.as_deref().unwrap_or(&[]), out);
+    }
+}
@@ -0,0 +1,136 @@
+//! Lossless parser for apache configuration
+
+use std::borrow::Cow;
+
+use nom::{character::complete::space0, combinator::opt};
+
+use super::{close_tag, lexer::*, quote_literal, Apache};
+use crate::{
+    cst::{self, text, Block, CstScheme, Node},
+    error::{Error, ErrorKind, ParseError},
+    lexer::{Escapes, Literal},
+};
+
+impl CstScheme for Apache {
+    const ESCAPES: Escapes = Escapes::Apache;
+
+    fn parse_cst(input: &[u8]) -> anyhow::Result<Block<Self>> {
+        let (rest, block) = parse_block(input).map_err(|err| ParseError::from_nom(input, err))?;
+        if !rest.is_empty() {
+            let offset = input.len() - rest.len();
+            return Err(ParseError::new(input, offset, ErrorKind::MismatchedCloseTag).into());
+        }
+        Ok(block)
+    }
+
+    fn quote(s: &str) -> Cow<'_, str> {
+        quote_literal(s)
+    }
+}
+
+fn unexpected<'a>(input: &'a [u8], expected: &[&str]) -> nom::Err<Error<&'a [u8]>> {
+    let (start, _) = trivia::<false>(input).unwrap_or((input, input));
+    let kind = if start.is_empty() {
+        ErrorKind::UnexpectedEof
+    } else {
+        ErrorKind::UnexpectedToken
+    };
+    Error::new(start, kind).expected(expected).cut()
+}
+
+/// Token at `input` with the trivia before it
+fn next_token<const NL: bool>(input: &[u8]) -> IResult<&[u8], (Token<'_>, cst::Token)> {
+    let (start, _) = trivia::<NL>(input)?;
+    let (rest, tok) = inner_tokenizer::<NL>(start)?;
+    let leading = text(input, start)?;
+    let raw = text(start, rest)?;
+    Ok((rest, (tok, cst::Token { leading, raw })))
+}
+
+/// Literal arguments, stops before the first other token
+fn parse_args<const NL: bool>(mut input: &[u8]) -> IResult<&[u8], Vec<cst::Token>> {
+    let mut result = vec![];
+    loop {
+        let (rest, (tok, token)) = next_token::<NL>(input)?;
+        if tok.literal().is_none() {
+            return Ok((input, result));
+        }
+        result.push(token);
+        input = rest;
+    }
+}
+
+fn parse_block(mut input: &[u8]) -> IResult<&[u8], Block<Apache>> {
+    let mut block = Block::default();
+    loop {
+        let (rest, (tok, mut name)) = next_token::<false>(input)?;
+        let node = match tok {
+            Token::CloseTag | Token::Eof => {
+                // leave the close tag to the caller
+                let (start, _) = trivia::<false>(input)?;
+                block.trailing = text(input, start)?;
+                return Ok((start, block));
+            }
+            Token::OpenTag => {
+                let (mut rest, (_, ident)) = next_token::<false>(rest)?;
+                name.leading = name.leading + &name.raw + &ident.leading;
+                name.raw = ident.raw;
+
+                let mut args = vec![];
+                if name.raw.eq_ignore_ascii_case("IfVersion") {
+                    let start = rest;
+                    let op;
+                    (rest, _) = space0(rest)?;
+                    let leading = text(start, rest)?;
+                    (rest, op) = opt(operator_str)(rest)?;
+                    if let Some(op) = op {
+                        args.push(cst::Token::new(leading, op));
+                    } else {
+                        rest = start;
+                    }
+                }
+                let (rest, more) = parse_args::<false>(rest)?;
+                args.extend(more);
+
+                let (end, (tok, head_end)) = next_token::<false>(rest)?;
+                if tok != Token::EndTag {
+                    return Err(unexpected(rest, &[">"]));
+                }
+                let (rest, body) = parse_block(end)?;
+
+                let (start, _) = trivia::<false>(rest)?;
+                let lit = Literal {
+                    escapes: Escapes::Apache,
+                    ..name.literal()
+                };
+                let (end, _) = close_tag(&lit.unescape(), start)?;
+                let tail = cst::Token {
+                    leading: text(rest, start)?,
+                    raw: text(start, end)?,
+                };
+                input = end;
+
+                Node {
+                    name,
+                    args,
+                    head_end,
+                    body: Some(body),
+                    tail,
+                }
+            }
+            Token::Literal(_) => {
+                let (rest, args) = parse_args::<true>(rest)?;
+                input = rest;
+                Node {
+                    name,
+                    args,
+                    head_end: Default::default(),
+                    body: None,
+                    tail: Default::default(),
+                }
+            }
+            _ => return Err(unexpected(input, &["directive"])),
+        };
+        block.nodes.push(node);
+    }
+}
@@ -1,4 +1,4 @@
-use nom::character::{complete::space0, streaming::anychar};
+use nom::character::complete::space0;
 #[allow(unused_imports)]
 use nom::{
     branch::alt,
@@ -9,12 +9,14 @@ use nom::{
         streaming::space1,
     },
     combinator::{fail, map, map_res, opt, value},
-    number::complete::be_u8,
     sequence::{delimited, tuple},
-    IResult,
 };
 
-use crate::lexer::Literal;
+pub use crate::error::IResult;
+use crate::{
+    error::{Error, ErrorKind},
+    lexer::{continuation, quoted, utf8, Escapes, Literal},
+};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Token<'a> {
@@ -58,19 +60,19 @@ pub fn operator_str(input: &[u8]) -> IResult<&[u8], &str> {
     )(input)
 }
 
-fn opentag(input: &[u8]) -> IResult<&[u8], Token> {
+fn opentag(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     value(Token::OpenTag, tag(b"<"))(input)
 }
 
-fn endtag(input: &[u8]) -> IResult<&[u8], Token> {
+fn endtag(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     value(Token::EndTag, tag(b">"))(input)
 }
 
-fn newline(input: &[u8]) -> IResult<&[u8], Token> {
+fn newline(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     value(Token::NewLine, tuple((opt(tag(b"\r")), tag(b"\n"))))(input)
 }
 
-fn closetag(input: &[u8]) -> IResult<&[u8], Token> {
+fn closetag(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     value(Token::CloseTag, tag(b"</"))(input)
 }
 
@@ -86,46 +88,47 @@ fn comment(input: &[u8]) -> IResult<&[u8], &[u8]> {
     )(input)
 }
 
-fn literal(input: &[u8]) -> IResult<&[u8], Token> {
-    let (_, mut first) = be_u8(input)?;
-
-    let (input, raw) = match first {
-        b'"' => map_res(
-            // for empty string
-            map(
-                delimited(
-                    cchar(first as _),
-                    opt(escaped(none_of(r#"\""#), '\\', anychar)),
-                    cchar(first as _),
-                ),
-                Option::unwrap_or_default,
-            ),
-            std::str::from_utf8,
-        )(input),
-        b'\'' => map_res(
-            // for empty string
-            map(
-                delimited(
-                    cchar(first as _),
-                    opt(escaped(none_of(r#"\'"#), '\\', anychar)),
-                    cchar(first as _),
-                ),
-                Option::unwrap_or_default,
-            ),
-            std::str::from_utf8,
-        )(input),
-        _ => {
-            first = 0;
-            map_res(
-                escaped(none_of(" \t\r\n<>'\"\\"), '\\', anychar),
-                std::str::from_utf8,
-            )(input)
-        }
+fn literal(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (rest, lit) = match input.first() {
+        Some(b'"' | b'\'') => quoted(input),
+        _ => word(input),
     }?;
-    Ok((input, Token::Literal(Literal { raw, quote: first })))
+    Ok((
+        rest,
+        Token::Literal(Literal {
+            escapes: Escapes::Apache,
+            ..lit
+        }),
+    ))
+}
+
+/// Unquoted literal, `\` escapes the next char, a line continuation is part of
+/// the word unless the next line starts with a blank
+fn word(input: &[u8]) -> IResult<&[u8], Literal<'_>> {
+    let mut len = 0;
+    while let Some(&b) = input.get(len) {
+        match b {
+            b'\\' => match continuation(&input[len..]) {
+                Some(n) => match input.get(len + n) {
+                    Some(b' ' | b'\t' | b'\r' | b'\n' | b'<' | b'>' | b'\'' | b'"') | None => break,
+                    Some(_) => len += n,
+                },
+                None => len = (len + 2).min(input.len()),
+            },
+            b' ' | b'\t' | b'\r' | b'\n' | b'<' | b'>' | b'\'' | b'"' => break,
+            _ => len += 1,
+        }
+    }
+    if len == 0 {
+        return Err(nom::Err::Error(Error::new(
+            input,
+            ErrorKind::UnexpectedToken,
+        )));
+    }
+    Ok((&input[len..], Literal::from(utf8(input, len)?)))
 }
 
-pub fn tokenizer(input: &[u8]) -> IResult<&[u8], Token> {
+pub fn tokenizer(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     inner_tokenizer::<false>(input)
 }
 
@@ -134,17 +137,32 @@ fn space_and_comment<const NL: bool>(input: &[u8]) -> IResult<&[u8], Option<&[u8
     map(tuple((space, opt(comment))), |x| x.1)(input)
 }
 
-pub fn inner_tokenizer<const NL: bool>(mut input: &[u8]) -> IResult<&[u8], Token> {
+/// Line continuations between tokens are whitespaces
+fn continuations(input: &[u8]) -> &[u8] {
+    let mut rest = input;
+    while let Some(n) = continuation(rest) {
+        rest = &rest[n..];
+    }
+    rest
+}
+
+/// Skip whitespaces (except newline if `NL`) and comments, returns the skipped text
+pub fn trivia<const NL: bool>(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let mut rest = input;
     loop {
-        let (rest, cmt) = space_and_comment::<NL>(input)?;
-        input = rest;
-        if cmt.is_some() {
-            // println!("[cmt] {:?}", std::str::from_utf8(cmt.unwrap()));
-        } else {
+        let (r, cmt) = space_and_comment::<NL>(rest)?;
+        let r2 = continuations(r);
+        rest = r2;
+        if cmt.is_none() && r2.len() == r.len() {
             break;
         }
     }
-    if input.len() == 0 {
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+pub fn inner_tokenizer<const NL: bool>(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (input, _) = trivia::<NL>(input)?;
+    if input.is_empty() {
         return Ok((input, Token::Eof));
     }
     alt((closetag, opentag, endtag, newline, literal))(input)
@@ -1,156 +1,339 @@
 //! Nom parser for apache configuration
+//!
+//! Like httpd, `\\` and `\` before the enclosing quote are unescaped, any other
+//! backslash is kept, so `<Files "\.ht*">` matches `\.ht*`. A `\` ending a line,
+//! even before trailing whitespaces, joins the next one.
+//!
+//! Relative include paths are in `ServerRoot`, set by the `ServerRoot` directive
+//! met before the include, or the root directory given to
+//! [`Config::resolve_include`](crate::ast::Config::resolve_include).
 
+mod cst;
 pub mod lexer;
 
-use std::path::Path;
-
 use crate::{
-    ast::{Directive, DirectiveTrait},
-    lexer::line_column2,
+    ast::{Directive, DirectiveTrait, IncludeContext, Span, Value},
+    error::{recover, Diagnostics, Error, ErrorKind, ParseError},
+    lexer::{Escapes, LineIndex, Literal, Position},
+    render::{indent, quote_with, Render},
     utils::*,
 };
 
 use self::lexer::*;
 
 use anyhow::Context;
-use nom::{
-    character::complete::space0,
-    combinator::{fail, map, map_opt, opt, verify},
-    sequence::tuple,
-    IResult,
-};
+use nom::{character::complete::space0, combinator::opt};
 
 #[derive(Debug, Clone, Default)]
 pub struct Apache;
 
+/// Parse configuration text into directives whose names and arguments are `T`,
+/// a [`Literal`] borrows them from `input` without any copy
+pub fn parse<'a, T>(input: &'a [u8]) -> anyhow::Result<Vec<Directive<Apache, T>>>
+where
+    T: Value + From<Literal<'a>>,
+{
+    let (rest, mut result) = parse_block(input, input, &mut None, &[])
+        .map_err(|err| ParseError::from_nom(input, err))?;
+    let (rest, _) = trivia::<false>(rest).map_err(|err| ParseError::from_nom(input, err))?;
+    if !rest.is_empty() {
+        let offset = input.len() - rest.len();
+        return Err(ParseError::new(input, offset, ErrorKind::MismatchedCloseTag).into());
+    }
+    let index = LineIndex::new(input);
+    for d in result.iter_mut() {
+        d.locate(&index);
+    }
+    Ok(result)
+}
+
 impl DirectiveTrait<Apache> for Directive<Apache> {
     fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>> {
-        let res = parse_block(input).map_err(|err| {
-            err.map(|e| {
-                let ((l, c), pos) = line_column2(input, e.input).unwrap();
-                anyhow::anyhow!("{pos}({l}:{c}) err: {:?}", e.code)
-            })
-        })?;
-        Ok(res.1)
+        parse(input)
+    }
+
+    fn parse_recovering(input: &[u8]) -> (Vec<Self>, Vec<ParseError>) {
+        let mut diag = Some(vec![]);
+        // every close tag is stray at the top level, so it only stops at the end
+        let mut result = match parse_block(input, input, &mut diag, &[]) {
+            Ok((_, result)) => result,
+            Err(err) => {
+                recover(&mut diag, err).ok();
+                vec![]
+            }
+        };
+
+        let index = LineIndex::new(input);
+        for d in result.iter_mut() {
+            d.locate(&index);
+        }
+        let errors = diag.unwrap_or_default();
+        let errors = errors.into_iter().map(|e| ParseError::from_error(input, e));
+        (result, errors.collect())
     }
 
     fn resolve_include_inner(
         mut self,
-        dir: &Path,
+        ctx: &mut IncludeContext,
         out: &mut Vec<Self>,
-        res: Option<ResolvePath>,
     ) -> anyhow::Result<()> {
         let optional = self.name.eq_ignore_ascii_case("IncludeOptional");
         if self.name.eq_ignore_ascii_case("include") || optional {
-            let path = Path::new(
-                self.args
-                    .get(0)
-                    .context("include directive expect one arg")?
-                    .as_str(),
-            );
-            for path in glob::glob(
-                &res.resolve(&if path.is_absolute() {
-                    path.to_path_buf()
-                } else {
-                    dir.join(path)
-                })?
-                .to_string_lossy(),
-            )?
-            .flatten()
-            {
-                let path = res.resolve(&path)?;
-                if optional && !path.exists() {
-                    continue;
-                }
-                let data = std::fs::read(&path)?;
-                for c in Self::parse(&data).with_context(|| format!("parse {path:?}"))? {
-                    c.resolve_include_inner(dir, out, res)?;
-                }
+            let pattern = self
+                .args
+                .first()
+                .context("include directive expect one arg")?;
+            let mut paths = glob_include(ctx.fs, pattern, &ctx.root, ctx.res)?;
+            if optional {
+                paths.retain(|path| ctx.fs.exists(path));
             }
+            self.resolve_files(paths, ctx, out)?;
         } else {
-            self.resolve_include(dir, res)?;
+            if self.name.eq_ignore_ascii_case("ServerRoot") {
+                // the following relative includes are in the new root
+                if let Some(root) = self.args.first() {
+                    ctx.root = root.into();
+                }
+            }
+            self.resolve_include(ctx)?;
             out.push(self);
         }
         Ok(())
     }
 }
 
-fn parse_block(mut input: &[u8]) -> IResult<&[u8], Vec<Directive<Apache>>> {
+impl<T: Value + AsRef<str>> Render for Directive<Apache, T> {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        indent(out, depth);
+        let name = quote_literal(self.name.as_ref());
+        if self.body().is_some() {
+            out.push('<');
+        }
+        out.push_str(&name);
+        for arg in &self.args {
+            out.push(' ');
+            out.push_str(&quote_literal(arg.as_ref()));
+        }
+        if let Some(children) = self.body() {
+            out.push_str(">\n");
+            children.render_to(out, depth + 1);
+            indent(out, depth);
+            out.push_str("</");
+            out.push_str(&name);
+            out.push('>');
+        }
+        out.push('\n');
+    }
+}
+
+/// Quote an argument for apache configuration if necessary
+pub fn quote_literal(s: &str) -> std::borrow::Cow<'_, str> {
+    quote_with(s, "<>", "#", Escapes::Apache)
+}
+
+/// Parse directives from `input`, which is a suffix of `base`, stops before
+/// the close tag of the section
+///
+/// When recovering, a directive with error is skipped to the end of line, and a
+/// close tag not matching any of the open `sections` is skipped.
+fn parse_block<'a, T: Value + From<Literal<'a>>>(
+    base: &'a [u8],
+    mut input: &'a [u8],
+    diag: &mut Diagnostics<'a>,
+    sections: &[&str],
+) -> IResult<&'a [u8], Vec<Directive<Apache, T>>> {
     let mut result = vec![];
     loop {
-        let (rest, tok) = tokenizer(input)?;
+        let (start, _) = trivia::<false>(input)?;
+        let (rest, tok) = tokenizer(start)?;
         match tok {
             Token::NewLine => {
                 input = rest;
                 continue;
             }
+            Token::CloseTag if diag.is_some() => {
+                let (_, tag) = tokenizer(rest)?;
+                let tag = tag.literal().map(|l| l.unescape()).unwrap_or_default();
+                if sections.iter().any(|s| s.eq_ignore_ascii_case(&tag)) {
+                    break;
+                }
+                let mut err =
+                    Error::new(start, ErrorKind::MismatchedCloseTag).found(format!("</{tag}>"));
+                if let Some(name) = sections.last() {
+                    err = err.expected(&[&format!("</{name}>")]);
+                }
+                recover(diag, err.cut())?;
+                input = skip_line(rest);
+                continue;
+            }
             Token::CloseTag | Token::Eof => break,
             _ => {}
         }
-        let res = parse_one(rest, tok)?;
-        result.push(res.1);
-        input = res.0;
+        match parse_one(base, start, rest, tok, diag, sections) {
+            Ok((rest, mut d)) => {
+                d.span.range = base.len() - start.len()..base.len() - rest.len();
+                result.push(d);
+                input = rest;
+            }
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) if diag.is_some() => {
+                input = skip_line(e.input);
+                recover(diag, nom::Err::Failure(e))?;
+            }
+            Err(err) => return Err(err),
+        }
     }
     Ok((input, result))
 }
 
-fn parse_one<'a>(input: &'a [u8], tok: Token<'a>) -> IResult<&'a [u8], Directive<Apache>> {
+/// Skip the rest of the line from an error at `input`
+fn skip_line(mut input: &[u8]) -> &[u8] {
+    loop {
+        let start = trivia::<true>(input).map_or(input, |x| x.0);
+        match inner_tokenizer::<true>(start) {
+            Ok((_, Token::Eof)) => return start,
+            Ok((rest, Token::NewLine)) => return rest,
+            Ok((rest, _)) => input = rest,
+            // like unterminated quote, try again from the next char
+            Err(_) => input = &start[1..],
+        }
+    }
+}
+
+/// Parse the directive started by `tok` at `start`, `input` is the rest after it
+fn parse_one<'a, T: Value + From<Literal<'a>>>(
+    base: &'a [u8],
+    start: &'a [u8],
+    input: &'a [u8],
+    tok: Token<'a>,
+    diag: &mut Diagnostics<'a>,
+    sections: &[&str],
+) -> IResult<&'a [u8], Directive<Apache, T>> {
     match tok {
-        Token::OpenTag => {
-            let (mut rest, name) = map_opt(tokenizer, |tok| tok.ident())(input)?;
-            let mut special = vec![];
-            if name.eq_ignore_ascii_case("IfVersion") {
-                let op;
-                (rest, _) = opt(space0)(rest)?;
-                (rest, op) = opt(lexer::operator_str)(rest)?;
-                op.map(|op| special.push(op.to_string()));
-            }
-            map(
-                tuple((
-                    parse_args::<false>,
-                    verify(tokenizer, |tok| tok == &Token::EndTag),
-                    parse_block,
-                    verify(tuple((tokenizer, tokenizer, tokenizer)), |&(b, tag, e)| {
-                        b == Token::CloseTag
-                            && tag
-                                .raw_string()
-                                .unwrap_or_default()
-                                .eq_ignore_ascii_case(name)
-                            && e == Token::EndTag
-                    }),
-                )),
-                move |(args, _, children, _)| {
-                    special.extend(args);
-                    Directive {
-                        name: name.into(),
-                        args: std::mem::take(&mut special),
-                        children: Some(children),
-                        ..Default::default()
-                    }
+        Token::OpenTag => parse_section(base, input, diag, sections),
+        Token::Literal(l) => {
+            let (rest, args) = parse_args::<true, _>(base, input)?;
+            let (args, values) = args.into_iter().unzip();
+            let d = Directive {
+                name: l.into(),
+                args: values,
+                children: None,
+                span: Span {
+                    name: Position::new(base.len() - start.len()),
+                    args,
+                    ..Default::default()
                 },
-            )(rest)
+                ..Default::default()
+            };
+            Ok((rest, d))
+        }
+        _ => {
+            let err = Error::new(start, ErrorKind::UnexpectedToken).expected(&["directive"]);
+            Err(err.cut())
         }
-        Token::Literal(l) => map(parse_args::<true>, |args| Directive {
-            name: l.raw.into(),
+    }
+}
+
+/// Parse a section after its `<`, `sections` are the names of the enclosing ones
+fn parse_section<'a, T: Value + From<Literal<'a>>>(
+    base: &'a [u8],
+    input: &'a [u8],
+    diag: &mut Diagnostics<'a>,
+    sections: &[&str],
+) -> IResult<&'a [u8], Directive<Apache, T>> {
+    let offset = |i: &[u8]| base.len() - i.len();
+
+    let (start, _) = trivia::<false>(input)?;
+    let (mut rest, tok) = tokenizer(start)?;
+    let lit = tok.literal().ok_or_else(|| {
+        Error::new(start, ErrorKind::UnexpectedToken)
+            .expected(&["section name"])
+            .cut()
+    })?;
+    let name = lit.unescape();
+    let mut args = vec![];
+    if name.eq_ignore_ascii_case("IfVersion") {
+        let op;
+        (rest, _) = opt(space0)(rest)?;
+        let pos = Position::new(offset(rest));
+        (rest, op) = opt(lexer::operator_str)(rest)?;
+        if let Some(op) = op {
+            args.push((pos, Literal::from(op).into()));
+        }
+    }
+    let (rest, more) = parse_args::<false, _>(base, rest)?;
+    args.extend(more);
+
+    let (end, _) = trivia::<false>(rest)?;
+    let (rest, tok) = tokenizer(end)?;
+    if tok != Token::EndTag {
+        let kind = if tok == Token::Eof {
+            ErrorKind::UnexpectedEof
+        } else {
+            ErrorKind::UnexpectedToken
+        };
+        return Err(Error::new(end, kind).expected(&[">"]).cut());
+    }
+
+    let mut open = sections.to_vec();
+    open.push(&name);
+    let (rest, children) = parse_block(base, rest, diag, &open)?;
+    // when recovering, a missing close tag ends the section anyway
+    let rest = match close_tag(&name, rest) {
+        Ok((rest, _)) => rest,
+        Err(err) => {
+            recover(diag, err)?;
+            rest
+        }
+    };
+
+    let (args, values) = args.into_iter().unzip();
+    let d = Directive {
+        name: lit.into(),
+        args: values,
+        children: Some(children),
+        span: Span {
+            name: Position::new(offset(start)),
             args,
-            children: None,
             ..Default::default()
-        })(input),
-        _ => fail(input),
+        },
+        ..Default::default()
+    };
+    Ok((rest, d))
+}
+
+/// Parse `</name>`, the names are compared unescaped
+fn close_tag<'a>(name: &str, input: &'a [u8]) -> IResult<&'a [u8], ()> {
+    let (start, _) = trivia::<false>(input)?;
+    let expected = format!("</{name}>");
+    let mismatched = || Error::new(start, ErrorKind::MismatchedCloseTag).expected(&[&expected]);
+
+    let (rest, tok) = tokenizer(start)?;
+    if tok != Token::CloseTag {
+        return Err(mismatched().cut());
+    }
+    let (rest, tag) = tokenizer(rest)?;
+    let (rest, end) = tokenizer(rest)?;
+    let tag = tag.literal().map(|l| l.unescape()).unwrap_or_default();
+    if !tag.eq_ignore_ascii_case(name) || end != Token::EndTag {
+        return Err(mismatched().found(format!("</{tag}>")).cut());
     }
+    Ok((rest, ()))
 }
 
-fn parse_args<const NL: bool>(mut input: &[u8]) -> IResult<&[u8], Vec<String>> {
+fn parse_args<'a, const NL: bool, T: From<Literal<'a>>>(
+    base: &'a [u8],
+    mut input: &'a [u8],
+) -> IResult<&'a [u8], Vec<(Position, T)>> {
     let mut result = vec![];
     loop {
-        let (rest, tok) = inner_tokenizer::<NL>(input)?;
+        let (start, _) = trivia::<NL>(input)?;
+        let (rest, tok) = inner_tokenizer::<NL>(start)?;
         if let Some(l) = tok.literal() {
-            result.push(l.into());
+            result.push((Position::new(base.len() - start.len()), l.into()));
         } else {
             break;
         }
         input = rest;
     }
-    // println!("[args] {result:?}");
     Ok((input, result))
 }
@@ -3,26 +3,37 @@
 use std::{
     fmt::Debug,
     marker::PhantomData,
+    ops::Range,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::Context;
 
 use crate::{
-    cpath::{CPath, Filter},
-    lexer::Literal,
+    cpath::{CPath, Cond, Filter, Item},
+    error::{ErrorKind, IncludeError, ParseError},
+    fs::{ConfigFs, StdFs},
+    lexer::{LineIndex, Literal, Position},
+    render::Render,
     utils::ResolvePath,
 };
 
-pub trait FromLiteral: Eq + PartialEq + for<'a> From<Literal<'a>> + Clone + Default {}
+/// Type of names and arguments, like `String`, or [`Literal`] borrowing the source
+pub trait Value: Eq + PartialEq + Clone + Default {}
 
-impl<T: Eq + PartialEq + for<'a> From<Literal<'a>> + Clone + Default> FromLiteral for T {}
+impl<T: Eq + PartialEq + Clone + Default> Value for T {}
+
+/// [`Value`] converted from a [`Literal`] of any lifetime, i.e. owning its text
+pub trait FromLiteral: Value + for<'a> From<Literal<'a>> {}
+
+impl<T: Value + for<'a> From<Literal<'a>>> FromLiteral for T {}
 
 #[derive(Debug, Clone, Default)]
 pub struct Config<S, T = String>
 where
     S: Clone + Default,
-    T: FromLiteral,
+    T: Value,
 {
     pub path: PathBuf,
     pub root: Directive<S, T>,
@@ -35,14 +46,17 @@ where
     T: FromLiteral,
 {
     pub fn parse(path: PathBuf) -> anyhow::Result<Self> {
-        let data = std::fs::read(&path)?;
+        Self::parse_in(&StdFs, path)
+    }
+
+    /// Parse the file `path` of `fs`
+    pub fn parse_in(fs: &dyn ConfigFs, path: PathBuf) -> anyhow::Result<Self> {
         Ok(Config {
-            path,
             root: Directive {
-                children: Some(Directive::parse(&data)?),
+                children: Some(Directive::parse_file_in(fs, &path)?),
                 ..Default::default()
             },
-            ..Default::default()
+            path,
         })
     }
 
@@ -53,15 +67,101 @@ where
             .expect("root must have children")
     }
 
+    /// Replace the include directives with the included files, relative include
+    /// paths are resolved in `root_dir`, by default the directory of the file
     pub fn resolve_include(
         &mut self,
         root_dir: Option<&Path>,
         res: Option<ResolvePath>,
     ) -> anyhow::Result<()> {
-        self.root
-            .resolve_include(root_dir.or(self.path.parent()).context("no root_dir")?, res)?;
+        let root = root_dir.or(self.path.parent()).context("no root_dir")?;
+        let mut ctx = IncludeContext::new(root);
+        ctx.res = res;
+        self.resolve_include_with(&mut ctx)
+    }
+
+    /// Like [`Config::resolve_include`], in an explicit context
+    pub fn resolve_include_with(&mut self, ctx: &mut IncludeContext) -> anyhow::Result<()> {
+        ctx.enter(&self.path)?;
+        let res = Directive::resolve_dropin(&mut self.root, &self.path, ctx)
+            .and_then(|_| self.root.resolve_include(ctx));
+        ctx.leave();
+        res
+    }
+}
+
+/// State of the include resolution, threaded through the included files
+///
+/// Relative include paths are resolved in `root`, as the servers do: the
+/// directory of the configuration file for nginx, see
+/// [`nginx::include_context`](crate::nginx::include_context), and `ServerRoot`
+/// for apache, which a `ServerRoot` directive met on the way changes.
+///
+/// A file including itself, directly or not, or files nested deeper than
+/// `max_depth` fail with an [`IncludeError`].
+#[derive(Clone)]
+pub struct IncludeContext<'a> {
+    pub root: PathBuf,
+    pub res: Option<ResolvePath<'a>>,
+    /// Where the included files are read, [`StdFs`] by default
+    pub fs: &'a dyn ConfigFs,
+    /// Maximum number of nested includes, 128 by default like apache
+    pub max_depth: usize,
+    /// Keep the include directives as transparent containers of the included
+    /// directives, see [`Directive::transparent`]
+    pub keep_includes: bool,
+    chain: Vec<PathBuf>,
+}
+
+impl IncludeContext<'_> {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            res: None,
+            fs: &StdFs,
+            max_depth: 128,
+            keep_includes: false,
+            chain: vec![],
+        }
+    }
+
+    /// Files being included, from the outermost one
+    pub fn chain(&self) -> &[PathBuf] {
+        &self.chain
+    }
+
+    /// Start including `path`, to be paired with [`IncludeContext::leave`]
+    pub fn enter(&mut self, path: &Path) -> anyhow::Result<()> {
+        let key = self.fs.canonicalize(path);
+        let mut chain = self.chain.clone();
+        chain.push(path.to_path_buf());
+        if self.chain.iter().any(|p| self.fs.canonicalize(p) == key) {
+            return Err(IncludeError::Cycle(chain).into());
+        }
+        // the outermost file is not included
+        if self.chain.len() > self.max_depth {
+            return Err(IncludeError::TooDeep(self.max_depth, chain).into());
+        }
+        self.chain = chain;
         Ok(())
     }
+
+    /// Done including the last entered file
+    pub fn leave(&mut self) {
+        self.chain.pop();
+    }
+}
+
+impl<S, T> Config<S, T>
+where
+    Directive<S, T>: Render,
+    S: Clone + Default,
+    T: Value,
+{
+    /// Render the configuration back to text
+    pub fn render(&self) -> String {
+        self.root.children.as_deref().unwrap_or(&[]).render()
+    }
 }
 
 pub trait DirectiveTrait<S, T = String>: Sized + AsMut<Directive<S, T>>
@@ -70,13 +170,47 @@ where
     S: Clone + Default,
     T: FromLiteral,
 {
+    /// Parse configuration text, a syntax error is a [`ParseError`]
+    /// which can be retrieved by `downcast_ref`
     fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>>;
 
-    fn resolve_include(&mut self, dir: &Path, res: Option<ResolvePath>) -> anyhow::Result<()> {
+    /// Parse configuration text, skipping the parts with syntax errors, returns
+    /// the directives which could be understood and every error met
+    ///
+    /// The default implementation stops at the first error without any directive.
+    fn parse_recovering(input: &[u8]) -> (Vec<Self>, Vec<ParseError>) {
+        match Self::parse(input) {
+            Ok(result) => (result, vec![]),
+            Err(err) => {
+                let err = err
+                    .downcast::<ParseError>()
+                    .unwrap_or_else(|_| ParseError::new(input, 0, ErrorKind::UnexpectedToken));
+                (vec![], vec![err])
+            }
+        }
+    }
+
+    /// Parse a file, the directives' spans refer to `path`
+    fn parse_file(path: &Path) -> anyhow::Result<Vec<Self>> {
+        Self::parse_file_in(&StdFs, path)
+    }
+
+    /// Parse the file `path` of `fs`
+    fn parse_file_in(fs: &dyn ConfigFs, path: &Path) -> anyhow::Result<Vec<Self>> {
+        let data = fs.read(path)?;
+        let mut result = Self::parse(&data).with_context(|| format!("parse {path:?}"))?;
+        let path = Arc::from(path);
+        for d in result.iter_mut() {
+            d.as_mut().set_path(&path);
+        }
+        Ok(result)
+    }
+
+    fn resolve_include(&mut self, ctx: &mut IncludeContext) -> anyhow::Result<()> {
         if let Some(childs) = self.as_mut().children.take() {
             let mut result = vec![];
             for c in childs {
-                c.resolve_include_inner(dir, &mut result, res)?;
+                c.resolve_include_inner(ctx, &mut result)?;
             }
             self.as_mut().children.replace(result);
         }
@@ -85,28 +219,195 @@ where
 
     fn resolve_include_inner(
         self,
-        dir: &Path,
+        ctx: &mut IncludeContext,
         out: &mut Vec<Self>,
-        res: Option<ResolvePath>,
     ) -> anyhow::Result<()>;
+
+    /// Merge the drop-in files of the configuration file `path` into `root`,
+    /// called by [`Config::resolve_include`] before resolving the includes
+    ///
+    /// The default implementation does nothing, as most formats have no drop-in.
+    fn resolve_dropin(
+        root: &mut Self,
+        path: &Path,
+        ctx: &mut IncludeContext,
+    ) -> anyhow::Result<()> {
+        let _ = (root, path, ctx);
+        Ok(())
+    }
+}
+
+impl<S, T> Directive<S, T>
+where
+    Directive<S, T>: DirectiveTrait<S, T>,
+    S: Clone + Default,
+    T: FromLiteral + AsRef<str>,
+{
+    /// Resolve the include directive `self` of the files `paths` into `out`:
+    /// their directives replace it, or it's kept as their transparent container
+    /// if [`IncludeContext::keep_includes`]
+    pub fn resolve_files(
+        mut self,
+        paths: Vec<PathBuf>,
+        ctx: &mut IncludeContext,
+        out: &mut Vec<Self>,
+    ) -> anyhow::Result<()> {
+        let site = Arc::new(Inclusion::new(&self));
+        let mut included = vec![];
+        for path in paths {
+            ctx.enter(&path)?;
+            let res = Self::parse_file_in(ctx.fs, &path).and_then(|list| {
+                list.into_iter().try_for_each(|mut c| {
+                    c.set_included(&site);
+                    c.resolve_include_inner(ctx, &mut included)
+                })
+            });
+            ctx.leave();
+            res?;
+        }
+        if ctx.keep_includes {
+            self.children = Some(included);
+            self.transparent = true;
+            out.push(self);
+        } else {
+            out.append(&mut included);
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Default, Eq)]
 pub struct Directive<S, T = String>
 where
     S: Clone + Default,
-    T: FromLiteral,
+    T: Value,
 {
     pub name: T,
     pub args: Vec<T>,
     pub children: Option<Vec<Directive<S, T>>>,
+    /// Body kept as text instead of children, like the lua code of nginx `*_by_lua_block`
+    pub raw_block: Option<RawBlock>,
+    pub span: Span,
+    /// An include directive kept with the included directives as children,
+    /// which the queries see through, see [`IncludeContext::keep_includes`]
+    pub transparent: bool,
     pub(crate) _scheme: PhantomData<S>,
 }
 
+/// Where [`Directive::insert`] puts the new directive, relative to a match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Before,
+    After,
+}
+
+/// A directive matched by a query, see [`Directive::query_matches`]
+#[derive(Debug)]
+pub struct Match<'a, S, T = String>
+where
+    S: Clone + Default,
+    T: Value,
+{
+    /// The directives enclosing `directive`, from the outermost to the parent,
+    /// excluding the queried one
+    pub ancestors: Vec<&'a Directive<S, T>>,
+    pub directive: &'a Directive<S, T>,
+}
+
+impl<'a, S, T> Match<'a, S, T>
+where
+    S: Clone + Default,
+    T: Value,
+{
+    fn new(ancestors: &[&'a Directive<S, T>], directive: &'a Directive<S, T>) -> Self {
+        Self {
+            ancestors: ancestors.to_vec(),
+            directive,
+        }
+    }
+
+    /// The directive enclosing the match, `None` for a direct child of the
+    /// queried one
+    pub fn parent(&self) -> Option<&'a Directive<S, T>> {
+        self.ancestors.last().copied()
+    }
+}
+
+/// Block body which is not in configuration syntax
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawBlock {
+    /// Text between the braces
+    pub source: String,
+    /// Byte range of `source`
+    pub range: Range<usize>,
+    /// Where `source` starts
+    pub start: Position,
+}
+
+impl RawBlock {
+    /// Parse `source` as lua code
+    pub fn parse_lua(&self) -> Result<luaparse::ast::Block<'_>, Box<luaparse::ParseError<'_>>> {
+        luaparse::parse(&self.source).map_err(Box::new)
+    }
+}
+
+/// Where a directive is located in the source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    /// Originating file, `None` if parsed from memory
+    pub path: Option<Arc<Path>>,
+    /// Byte range of the whole directive, including its children
+    pub range: Range<usize>,
+    pub name: Position,
+    pub args: Vec<Position>,
+    /// The include directive which brought the file in, `None` in the main file
+    pub included: Option<Arc<Inclusion>>,
+}
+
+impl Span {
+    /// The include directives which brought the directive in, from the
+    /// innermost one
+    pub fn include_trace(&self) -> impl Iterator<Item = &Inclusion> {
+        std::iter::successors(self.included.as_deref(), |i| i.span.included.as_deref())
+    }
+}
+
+/// An include directive, as recorded in the span of the included directives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inclusion {
+    pub name: String,
+    pub args: Vec<String>,
+    pub span: Span,
+}
+
+impl Inclusion {
+    pub fn new<S, T>(d: &Directive<S, T>) -> Self
+    where
+        S: Clone + Default,
+        T: Value + AsRef<str>,
+    {
+        Self {
+            name: d.name.as_ref().into(),
+            args: d.args.iter().map(|a| a.as_ref().into()).collect(),
+            span: d.span.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    /// Formats as `file:line:col` of the directive name
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = self.path.as_ref() {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{}", self.name)
+    }
+}
+
 impl<S: Debug, T: Debug> Debug for Directive<S, T>
 where
     S: Clone + Default,
-    T: FromLiteral,
+    T: Value,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut ds = f.debug_struct("Directive");
@@ -114,6 +415,9 @@ where
         if let Some(children) = self.children.as_ref() {
             ds.field("children", children);
         }
+        if let Some(raw) = self.raw_block.as_ref() {
+            ds.field("raw_block", &raw.source);
+        }
         ds.finish()
     }
 }
@@ -121,17 +425,21 @@ where
 impl<S, T> PartialEq for Directive<S, T>
 where
     S: Clone + Default,
-    T: FromLiteral,
+    T: Value,
 {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name && self.args == other.args && self.children == other.children
+        self.name == other.name
+            && self.args == other.args
+            && self.children == other.children
+            && self.raw_block.as_ref().map(|b| &b.source)
+                == other.raw_block.as_ref().map(|b| &b.source)
     }
 }
 
 impl<S, T> AsMut<Self> for Directive<S, T>
 where
     S: Clone + Default,
-    T: FromLiteral,
+    T: Value,
 {
     fn as_mut(&mut self) -> &mut Self {
         self
@@ -141,17 +449,120 @@ where
 impl<S, T> Directive<S, T>
 where
     S: Clone + Default,
-    T: FromLiteral + AsRef<str>,
+    T: Value,
+{
+    /// Simple directive without children
+    pub fn new(name: impl Into<T>, args: impl IntoIterator<Item = impl Into<T>>) -> Self {
+        Self {
+            name: name.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Block directive with no children yet
+    pub fn block(name: impl Into<T>, args: impl IntoIterator<Item = impl Into<T>>) -> Self {
+        Self {
+            children: Some(vec![]),
+            ..Self::new(name, args)
+        }
+    }
+
+    pub fn set_args(&mut self, args: impl IntoIterator<Item = impl Into<T>>) {
+        self.args = args.into_iter().map(Into::into).collect();
+    }
+
+    pub fn push_arg(&mut self, arg: impl Into<T>) {
+        self.args.push(arg.into());
+    }
+
+    /// The children, a simple directive becomes an empty block, so only for
+    /// adding children
+    pub fn children_mut(&mut self) -> &mut Vec<Self> {
+        self.children.get_or_insert_with(Vec::new)
+    }
+
+    /// Append a child, returns it for further editing
+    pub fn push_child(&mut self, child: Self) -> &mut Self {
+        let children = self.children_mut();
+        children.push(child);
+        children.last_mut().unwrap()
+    }
+
+    /// Insert a child at `index`, returns it for further editing
+    pub fn insert_child(&mut self, index: usize, child: Self) -> &mut Self {
+        let children = self.children_mut();
+        children.insert(index, child);
+        &mut children[index]
+    }
+
+    /// Set the originating file of this directive and its children
+    pub fn set_path(&mut self, path: &Arc<Path>) {
+        self.span.path = Some(path.clone());
+        for c in self.children.iter_mut().flatten() {
+            c.set_path(path);
+        }
+    }
+
+    /// Set the include directive which brought this directive and its children
+    pub(crate) fn set_included(&mut self, site: &Arc<Inclusion>) {
+        self.span.included = Some(site.clone());
+        for c in self.children.iter_mut().flatten() {
+            c.set_included(site);
+        }
+    }
+
+    /// The children written as a block, a kept include has none
+    pub(crate) fn body(&self) -> Option<&[Self]> {
+        self.children.as_deref().filter(|_| !self.transparent)
+    }
+
+    /// Fill line and column of the span positions from their offsets
+    pub(crate) fn locate(&mut self, index: &LineIndex) {
+        index.locate(&mut self.span.name);
+        for pos in self.span.args.iter_mut() {
+            index.locate(pos);
+        }
+        if let Some(raw) = self.raw_block.as_mut() {
+            index.locate(&mut raw.start);
+        }
+        for c in self.children.iter_mut().flatten() {
+            c.locate(index);
+        }
+    }
+}
+
+impl<S, T> Directive<S, T>
+where
+    S: Clone + Default,
+    T: Value + AsRef<str>,
 {
     pub fn query(&self, path: &str) -> Vec<Self> {
+        self.query_ref(path).into_iter().cloned().collect()
+    }
+
+    /// Like [`Self::query`], but borrows the matched directives
+    pub fn query_ref(&self, path: &str) -> Vec<&Self> {
         let mut result = vec![];
-        if let Some(childs) = self.children.as_ref() {
-            Self::inner_query(childs, path, &mut result);
-        }
+        Self::inner_query(self.childs(), path, &mut vec![], &mut |_, d| result.push(d));
         result
     }
 
-    fn inner_query(dirs: &[Self], path: &str, out: &mut Vec<Self>) {
+    /// Like [`Self::query_ref`], with the directives enclosing every match
+    pub fn query_matches(&self, path: &str) -> Vec<Match<'_, S, T>> {
+        let mut result = vec![];
+        Self::inner_query(self.childs(), path, &mut vec![], &mut |ancestors, d| {
+            result.push(Match::new(ancestors, d))
+        });
+        result
+    }
+
+    fn inner_query<'a>(
+        dirs: &'a [Self],
+        path: &str,
+        ancestors: &mut Vec<&'a Self>,
+        out: &mut dyn FnMut(&[&'a Self], &'a Self),
+    ) {
         let mut pathitem = path;
         let mut rest = None;
         if let Some((i, r)) = path.split_once("/") {
@@ -159,50 +570,363 @@ where
             rest.replace(r);
         }
         for d in dirs.iter() {
+            if d.transparent {
+                Self::inner_query(d.childs(), path, ancestors, out);
+                continue;
+            }
             if d.name.as_ref().eq_ignore_ascii_case(pathitem) {
                 if let Some(path) = rest {
-                    Self::inner_query(
-                        d.children.as_ref().map(Vec::as_slice).unwrap_or(&[]),
-                        path,
-                        out,
-                    );
+                    ancestors.push(d);
+                    Self::inner_query(d.childs(), path, ancestors, out);
+                    ancestors.pop();
                 } else {
-                    out.push(d.clone());
+                    out(ancestors, d);
                 }
             }
         }
     }
 
     pub fn cpath_query(&self, path: &CPath) -> Vec<Self> {
+        self.cpath_query_ref(path).into_iter().cloned().collect()
+    }
+
+    /// Like [`Self::cpath_query`], but borrows the matched directives
+    pub fn cpath_query_ref(&self, path: &CPath) -> Vec<&Self> {
         let mut result = vec![];
-        if let Some(childs) = self.children.as_ref() {
-            Self::inner_cpath_query(childs, path, &mut result);
-        }
+        Self::inner_cpath_query(self.childs(), path, &mut vec![], &mut |_, d| result.push(d));
         result
     }
 
-    fn inner_cpath_query(dirs: &[Self], path: &CPath, out: &mut Vec<Self>) {
+    /// Like [`Self::cpath_query_ref`], with the directives enclosing every match
+    pub fn cpath_matches(&self, path: &CPath) -> Vec<Match<'_, S, T>> {
+        let mut result = vec![];
+        Self::inner_cpath_query(self.childs(), path, &mut vec![], &mut |ancestors, d| {
+            result.push(Match::new(ancestors, d))
+        });
+        result
+    }
+
+    fn inner_cpath_query<'a>(
+        dirs: &'a [Self],
+        path: &CPath,
+        ancestors: &mut Vec<&'a Self>,
+        out: &mut dyn FnMut(&[&'a Self], &'a Self),
+    ) {
         let (item, rest, anylevel) = match path.peek() {
             Some(x) => x,
             _ => return,
         };
 
         for d in dirs.iter() {
-            let childs = d.children.as_ref().map(Vec::as_slice).unwrap_or(&[]);
-            if d.match_filter(&item.filter) {
+            if d.transparent {
+                Self::inner_cpath_query(d.childs(), path, ancestors, out);
+                continue;
+            }
+            ancestors.push(d);
+            if d.match_item(item) {
                 // leaf match
                 if rest.is_empty() {
-                    out.push(d.clone());
+                    out(&ancestors[..ancestors.len() - 1], d);
                 } else {
-                    Self::inner_cpath_query(childs, rest, out);
+                    Self::inner_cpath_query(d.childs(), rest, ancestors, out);
                 }
             }
             if anylevel {
-                Self::inner_cpath_query(childs, path, out);
+                Self::inner_cpath_query(d.childs(), path, ancestors, out);
             }
+            ancestors.pop();
         }
     }
 
+    /// Like [`Self::query`], but borrows the matched directives mutably
+    pub fn query_mut(&mut self, path: &str) -> Vec<&mut Self> {
+        let mut result = vec![];
+        if let Some(childs) = self.children.as_mut() {
+            Self::inner_query_mut(childs, path, &mut result);
+        }
+        result
+    }
+
+    fn inner_query_mut<'a>(dirs: &'a mut [Self], path: &str, out: &mut Vec<&'a mut Self>) {
+        let (pathitem, rest) = match path.split_once("/") {
+            Some((i, r)) => (i, Some(r)),
+            None => (path, None),
+        };
+        for d in dirs.iter_mut() {
+            if d.transparent {
+                Self::inner_query_mut(d.children.as_deref_mut().unwrap_or(&mut []), path, out);
+                continue;
+            }
+            if d.name.as_ref().eq_ignore_ascii_case(pathitem) {
+                if let Some(path) = rest {
+                    Self::inner_query_mut(d.children.as_deref_mut().unwrap_or(&mut []), path, out);
+                } else {
+                    out.push(d);
+                }
+            }
+        }
+    }
+
+    /// Like [`Self::cpath_query`], but borrows the matched directives mutably
+    ///
+    /// A directive is borrowed once even if matched more than once, and the
+    /// descendants of a matched directive are left out.
+    pub fn cpath_query_mut(&mut self, path: &CPath) -> Vec<&mut Self> {
+        let paths = self.cpath_indexes(path);
+        let mut result = vec![];
+        if let Some(childs) = self.children.as_mut() {
+            Self::borrow_indexes(childs, &paths, 0, &mut result);
+        }
+        result
+    }
+
+    /// Index paths of the directives matching `path`, in document order and
+    /// without the descendants of another match
+    fn cpath_indexes(&self, path: &CPath) -> Vec<Vec<usize>> {
+        let mut result = vec![];
+        Self::inner_cpath_indexes(self.childs(), path, &mut vec![], &mut result);
+        result.sort();
+        let mut outer: Vec<Vec<usize>> = vec![];
+        for p in result {
+            if !outer.last().is_some_and(|l| p.starts_with(l)) {
+                outer.push(p);
+            }
+        }
+        outer
+    }
+
+    fn inner_cpath_indexes(
+        dirs: &[Self],
+        path: &CPath,
+        prefix: &mut Vec<usize>,
+        out: &mut Vec<Vec<usize>>,
+    ) {
+        let (item, rest, anylevel) = match path.peek() {
+            Some(x) => x,
+            _ => return,
+        };
+
+        for (i, d) in dirs.iter().enumerate() {
+            prefix.push(i);
+            if d.transparent {
+                Self::inner_cpath_indexes(d.childs(), path, prefix, out);
+                prefix.pop();
+                continue;
+            }
+            if d.match_item(item) {
+                if rest.is_empty() {
+                    out.push(prefix.clone());
+                } else {
+                    Self::inner_cpath_indexes(d.childs(), rest, prefix, out);
+                }
+            }
+            if anylevel {
+                Self::inner_cpath_indexes(d.childs(), path, prefix, out);
+            }
+            prefix.pop();
+        }
+    }
+
+    /// Borrow the directives at the sorted index `paths` from `dirs`, which
+    /// are the children at `depth`
+    fn borrow_indexes<'a>(
+        dirs: &'a mut [Self],
+        mut paths: &[Vec<usize>],
+        depth: usize,
+        out: &mut Vec<&'a mut Self>,
+    ) {
+        for (i, d) in dirs.iter_mut().enumerate() {
+            let n = paths.iter().take_while(|p| p[depth] == i).count();
+            let (group, others) = paths.split_at(n);
+            paths = others;
+            match group.first() {
+                None => {}
+                Some(p) if p.len() == depth + 1 => out.push(d),
+                Some(_) => Self::borrow_indexes(
+                    d.children.as_deref_mut().unwrap_or(&mut []),
+                    group,
+                    depth + 1,
+                    out,
+                ),
+            }
+        }
+    }
+
+    /// Replace every directive matching the query `path` with `new`, returns
+    /// how many are replaced
+    pub fn replace(&mut self, path: &str, new: Self) -> usize {
+        let found = self.query_mut(path);
+        let count = found.len();
+        for d in found {
+            *d = new.clone();
+        }
+        count
+    }
+
+    /// Replace every directive matching `path` with `new`, returns how many are
+    /// replaced
+    pub fn cpath_replace(&mut self, path: &CPath, new: Self) -> usize {
+        let found = self.cpath_query_mut(path);
+        let count = found.len();
+        for d in found {
+            *d = new.clone();
+        }
+        count
+    }
+
+    /// Remove every directive matching the query `path`, returns them
+    pub fn remove(&mut self, path: &str) -> Vec<Self> {
+        let (parents, name) = match path.rsplit_once("/") {
+            Some((parent, name)) => (self.query_mut(parent), name),
+            None => (vec![self], path),
+        };
+        let mut result = vec![];
+        for parent in parents {
+            if let Some(children) = parent.children.as_mut() {
+                Self::remove_named(children, name, &mut result);
+            }
+        }
+        result
+    }
+
+    fn remove_named(children: &mut Vec<Self>, name: &str, out: &mut Vec<Self>) {
+        let (removed, kept) = std::mem::take(children)
+            .into_iter()
+            .partition(|c| !c.transparent && c.name.as_ref().eq_ignore_ascii_case(name));
+        *children = kept;
+        out.extend::<Vec<_>>(removed);
+        for c in children.iter_mut().filter(|c| c.transparent) {
+            if let Some(children) = c.children.as_mut() {
+                Self::remove_named(children, name, out);
+            }
+        }
+    }
+
+    /// Remove every directive matching `path`, returns them in document order
+    pub fn cpath_remove(&mut self, path: &CPath) -> Vec<Self> {
+        let mut result = vec![];
+        // from the last one, so that the indexes of the others are kept
+        for p in self.cpath_indexes(path).iter().rev() {
+            let (last, parents) = p.split_last().unwrap();
+            if let Some(children) = self.children_at(parents) {
+                result.push(children.remove(*last));
+            }
+        }
+        result.reverse();
+        result
+    }
+
+    /// Insert `new` before or after every directive matching the query `path`,
+    /// returns how many are inserted
+    pub fn insert(&mut self, path: &str, at: Placement, new: Self) -> usize {
+        let (parents, name) = match path.rsplit_once("/") {
+            Some((parent, name)) => (self.query_mut(parent), name),
+            None => (vec![self], path),
+        };
+        let mut count = 0;
+        for parent in parents {
+            if let Some(children) = parent.children.as_mut() {
+                count += Self::insert_named(children, name, at, &new);
+            }
+        }
+        count
+    }
+
+    fn insert_named(children: &mut Vec<Self>, name: &str, at: Placement, new: &Self) -> usize {
+        let mut count = 0;
+        let mut i = 0;
+        while i < children.len() {
+            let c = &mut children[i];
+            if c.transparent {
+                if let Some(children) = c.children.as_mut() {
+                    count += Self::insert_named(children, name, at, new);
+                }
+            } else if c.name.as_ref().eq_ignore_ascii_case(name) {
+                let index = match at {
+                    Placement::Before => i,
+                    Placement::After => i + 1,
+                };
+                children.insert(index, new.clone());
+                count += 1;
+                // skip the match and the inserted directive
+                i += 1;
+            }
+            i += 1;
+        }
+        count
+    }
+
+    /// Insert `new` before or after every directive matching `path`, returns
+    /// how many are inserted
+    pub fn cpath_insert(&mut self, path: &CPath, at: Placement, new: Self) -> usize {
+        let paths = self.cpath_indexes(path);
+        // from the last one, so that the indexes of the others are kept
+        for p in paths.iter().rev() {
+            let (last, parents) = p.split_last().unwrap();
+            let index = match at {
+                Placement::Before => *last,
+                Placement::After => last + 1,
+            };
+            if let Some(children) = self.children_at(parents) {
+                children.insert(index, new.clone());
+            }
+        }
+        paths.len()
+    }
+
+    /// The children of the descendant at the index path `parents`
+    fn children_at(&mut self, parents: &[usize]) -> Option<&mut Vec<Self>> {
+        // the indexes are of existing children
+        let mut children = self.children.as_mut();
+        for &i in parents {
+            children = children.and_then(|c| c[i].children.as_mut());
+        }
+        children
+    }
+
+    fn match_item(&self, item: &Item) -> bool {
+        self.match_filter(&item.filter)
+            && item
+                .cond
+                .as_deref()
+                .is_none_or(|cond| self.match_cond(cond))
+    }
+
+    fn match_cond(&self, cond: &Cond) -> bool {
+        match cond {
+            Cond::Exists(re) => match re.as_str().parse::<usize>() {
+                Ok(i) => i < self.args.len(),
+                Err(_) => self.visible_childs().any(|c| re.is_match(c.name.as_ref())),
+            },
+            Cond::ChildExists(item) => self.visible_childs().any(|c| c.match_item(item)),
+            Cond::Equal { name, value } => self.cond_values(name).any(|v| v == value),
+            Cond::Match { name, regex } => self.cond_values(name).any(|v| regex.is_match(v)),
+        }
+    }
+
+    /// Values a condition `name` refers to, see [`Cond`]
+    fn cond_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        let index = name.parse::<usize>().ok();
+        let args = index.and_then(|i| self.args.get(i)).map(AsRef::as_ref);
+        let childs = self
+            .visible_childs()
+            .filter(move |c| index.is_none() && c.name.as_ref().eq_ignore_ascii_case(name))
+            .flat_map(|c| c.args.iter().map(AsRef::as_ref));
+        args.into_iter().chain(childs)
+    }
+
+    fn childs(&self) -> &[Self] {
+        self.children.as_deref().unwrap_or(&[])
+    }
+
+    /// The children, with the ones of a transparent include in place of it
+    fn visible_childs(&self) -> Box<dyn Iterator<Item = &Self> + '_> {
+        Box::new(self.childs().iter().flat_map(|c| match c.transparent {
+            true => c.visible_childs(),
+            false => Box::new(std::iter::once(c)),
+        }))
+    }
+
     fn match_filter(&self, filter: &Filter) -> bool {
         match filter {
             Filter::Eq(n) => self.name.as_ref().eq_ignore_ascii_case(n),
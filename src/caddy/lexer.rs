@@ -0,0 +1,158 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till, take_while1},
+    character::{complete::space0, is_newline},
+    combinator::{map, opt, value},
+    sequence::tuple,
+};
+
+pub use crate::error::IResult;
+use crate::{
+    error::{Error, ErrorKind},
+    lexer::{quoted, unquoted, utf8, Literal},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Token<'a> {
+    BlockStart,
+    BlockEnd,
+    NewLine,
+    Eof,
+    Literal(Literal<'a>),
+    /// `<<MARKER` text, `body` is the lines before the closing marker, each of
+    /// them starts with `indent`, the whitespaces before the closing marker
+    Heredoc {
+        body: &'a str,
+        indent: &'a str,
+    },
+}
+
+impl<'a> Token<'a> {
+    pub fn literal(&self) -> Option<Literal<'a>> {
+        match self {
+            Self::Literal(l) => Some(*l),
+            _ => None,
+        }
+    }
+
+    /// Value of a literal or heredoc
+    pub fn value(&self) -> Option<String> {
+        match self {
+            Self::Literal(l) => Some((*l).into()),
+            Self::Heredoc { body, indent } => {
+                let lines = body
+                    .split('\n')
+                    .map(|l| l.strip_prefix(indent).unwrap_or(l))
+                    .collect::<Vec<_>>();
+                Some(lines.join("\n"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether `input` is at the end of a token
+fn token_end(input: &[u8]) -> bool {
+    input.first().is_none_or(u8::is_ascii_whitespace)
+}
+
+fn starttag(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (rest, tok) = value(Token::BlockStart, tag(b"{"))(input)?;
+    if !token_end(rest) {
+        // placeholder like `{host}`
+        return Err(nom::Err::Error(Error::new(
+            input,
+            ErrorKind::UnexpectedToken,
+        )));
+    }
+    Ok((rest, tok))
+}
+
+fn endtag(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (rest, tok) = value(Token::BlockEnd, tag(b"}"))(input)?;
+    if !token_end(rest) {
+        return Err(nom::Err::Error(Error::new(
+            input,
+            ErrorKind::UnexpectedToken,
+        )));
+    }
+    Ok((rest, tok))
+}
+
+fn newline(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    value(Token::NewLine, tuple((opt(tag(b"\r")), tag(b"\n"))))(input)
+}
+
+/// Comment till the end of line, excluding the newline
+fn comment(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    map(tuple((tag("#"), take_till(is_newline))), |x| x.1)(input)
+}
+
+/// Raw literal in backticks, without escapes
+fn backtick(input: &[u8]) -> IResult<&[u8], Literal<'_>> {
+    let body = &input[1..];
+    let len = body
+        .iter()
+        .position(|&b| b == b'`')
+        .ok_or_else(|| Error::new(input, ErrorKind::UnterminatedQuote).cut())?;
+    let raw = utf8(body, len)?;
+    let lit = Literal {
+        raw,
+        quote: b'`',
+        ..Default::default()
+    };
+    Ok((&body[len + 1..], lit))
+}
+
+fn heredoc(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (rest, (_, marker, _, _)) = tuple((
+        tag(b"<<"),
+        take_while1(|b: u8| b.is_ascii_alphanumeric() || b == b'_'),
+        opt(tag(b"\r")),
+        tag(b"\n"),
+    ))(input)?;
+
+    let mut line = rest;
+    loop {
+        let len = line.iter().position(|&b| b == b'\n').unwrap_or(line.len());
+        let content = line[..len].trim_ascii_start();
+        // the closing marker may be followed by more tokens
+        if content.starts_with(marker) && token_end(&content[marker.len()..]) {
+            let indent = utf8(line, len - content.len())?;
+            // without the newline before the closing line
+            let body_len = (rest.len() - line.len()).saturating_sub(1);
+            let body = utf8(rest, body_len)?;
+            let body = body.strip_suffix('\r').unwrap_or(body);
+            let end = &line[len - content.len() + marker.len()..];
+            return Ok((end, Token::Heredoc { body, indent }));
+        }
+        if len == line.len() {
+            return Err(Error::new(input, ErrorKind::UnterminatedQuote).cut());
+        }
+        line = &line[len + 1..];
+    }
+}
+
+fn literal(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (rest, lit) = match input.first() {
+        Some(b'"') => quoted(input),
+        Some(b'`') => backtick(input),
+        _ => unquoted(input, " \t\r\n\"`\\"),
+    }?;
+    Ok((rest, Token::Literal(lit)))
+}
+
+/// Skip whitespaces and comment in the line, returns the skipped text
+pub fn trivia(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (rest, _) = tuple((space0, opt(comment)))(input)?;
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+pub fn tokenizer(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (input, _) = trivia(input)?;
+    if input.is_empty() {
+        return Ok((input, Token::Eof));
+    }
+
+    alt((starttag, endtag, newline, heredoc, literal))(input)
+}
@@ -0,0 +1,342 @@
+//! Nom parser for Caddyfile
+//!
+//! A site block is a directive named by its first address, with the other addresses
+//! as arguments, the global options block is a directive with an empty name, and a
+//! snippet `(name)` is a directive named `(name)`. A site without braces is kept as
+//! its address followed by its directives at the top level.
+
+pub mod lexer;
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+
+use crate::{
+    ast::{Directive, DirectiveTrait, IncludeContext, Inclusion, Value},
+    error::{Error, ErrorKind, IncludeError, ParseError},
+    lexer::{LineIndex, Position},
+    render::{indent, quote, Render},
+    utils::*,
+};
+
+use self::lexer::*;
+
+#[derive(Debug, Clone, Default)]
+pub struct Caddy;
+
+impl DirectiveTrait<Caddy> for Directive<Caddy> {
+    fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>> {
+        let (rest, mut result) =
+            parse_block(input, input).map_err(|err| ParseError::from_nom(input, err))?;
+        let (rest, _) = trivia(rest).map_err(|err| ParseError::from_nom(input, err))?;
+        if !rest.is_empty() {
+            let offset = input.len() - rest.len();
+            return Err(ParseError::new(input, offset, ErrorKind::UnbalancedBrace).into());
+        }
+        let index = LineIndex::new(input);
+        for d in result.iter_mut() {
+            if d.children.is_some() && !d.name.starts_with('(') {
+                split_addresses(d);
+            }
+            d.locate(&index);
+        }
+        Ok(result)
+    }
+
+    /// Replace `import` with the snippet or the files it refers to
+    fn resolve_include(&mut self, ctx: &mut IncludeContext) -> anyhow::Result<()> {
+        if let Some(childs) = self.children.take() {
+            // snippets can be imported before their definition
+            let mut snippets = HashMap::new();
+            for c in childs.iter() {
+                register_snippet(c, &mut snippets);
+            }
+            let mut result = vec![];
+            for c in childs {
+                import(c, ctx, &mut result, &mut snippets, &mut vec![])?;
+            }
+            self.children.replace(result);
+        }
+        Ok(())
+    }
+
+    fn resolve_include_inner(
+        self,
+        ctx: &mut IncludeContext,
+        out: &mut Vec<Self>,
+    ) -> anyhow::Result<()> {
+        import(self, ctx, out, &mut HashMap::new(), &mut vec![])
+    }
+}
+
+/// Snippet name to its directives
+type Snippets = HashMap<String, Vec<Directive<Caddy>>>;
+
+/// `expanding` is the stack of the snippets being imported, from the outermost one
+fn import(
+    mut d: Directive<Caddy>,
+    ctx: &mut IncludeContext,
+    out: &mut Vec<Directive<Caddy>>,
+    snippets: &mut Snippets,
+    expanding: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    if d.name != "import" {
+        register_snippet(&d, snippets);
+        if let Some(childs) = d.children.take() {
+            let mut result = vec![];
+            for c in childs {
+                import(c, ctx, &mut result, snippets, expanding)?;
+            }
+            d.children.replace(result);
+        }
+        out.push(d);
+        return Ok(());
+    }
+
+    let target = d.args.first().context("import directive expect one arg")?;
+    let args = &d.args[1..];
+    if let Some(body) = snippets.get(target).cloned() {
+        let chain = expanding
+            .iter()
+            .chain([target])
+            .map(|name| PathBuf::from(format!("({name})")))
+            .collect::<Vec<_>>();
+        if expanding.contains(target) {
+            return Err(IncludeError::Cycle(chain).into());
+        }
+        if expanding.len() >= ctx.max_depth {
+            return Err(IncludeError::TooDeep(ctx.max_depth, chain).into());
+        }
+        expanding.push(target.clone());
+        let res = body
+            .into_iter()
+            .try_for_each(|c| import(substitute(c, args), ctx, out, snippets, expanding));
+        expanding.pop();
+        return res;
+    }
+
+    let paths = glob_include(ctx.fs, target, &ctx.root, ctx.res)?;
+    let found = !paths.is_empty();
+    let site = Arc::new(Inclusion::new(&d));
+    let mut imported = vec![];
+    for path in paths {
+        ctx.enter(&path)?;
+        let res = Directive::parse_file_in(ctx.fs, &path).and_then(|list| {
+            list.into_iter().try_for_each(|mut c| {
+                c.set_included(&site);
+                import(substitute(c, args), ctx, &mut imported, snippets, expanding)
+            })
+        });
+        ctx.leave();
+        res?;
+    }
+    if !found && !target.contains(['*', '?', '[']) {
+        anyhow::bail!("import {target:?}: no such snippet or file");
+    }
+    if ctx.keep_includes {
+        d.children = Some(imported);
+        d.transparent = true;
+        out.push(d);
+    } else {
+        out.append(&mut imported);
+    }
+    Ok(())
+}
+
+fn register_snippet(d: &Directive<Caddy>, snippets: &mut Snippets) {
+    if let Some(name) = d.name.strip_prefix('(').and_then(|n| n.strip_suffix(')')) {
+        snippets.insert(name.into(), d.children.clone().unwrap_or_default());
+    }
+}
+
+/// Replace the `{args[N]}` placeholders of imported directives
+fn substitute(mut d: Directive<Caddy>, args: &[String]) -> Directive<Caddy> {
+    d.name = replace_args(&d.name, args);
+    let mut result = vec![];
+    for a in d.args.iter() {
+        match a.as_str() {
+            // all arguments as separate tokens
+            "{args[:]}" => result.extend(args.iter().cloned()),
+            _ => result.push(replace_args(a, args)),
+        }
+    }
+    d.args = result;
+    if let Some(childs) = d.children.take() {
+        d.children = Some(childs.into_iter().map(|c| substitute(c, args)).collect());
+    }
+    d
+}
+
+/// Replace `{args[N]}`, `{args.N}` and ranges like `{args[1:]}` in `s`
+fn replace_args(s: &str, args: &[String]) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find("{args") {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i + 5..];
+        let Some(end) = tail.find('}') else {
+            break;
+        };
+        let index = &tail[..end];
+        let index = index
+            .strip_prefix('[')
+            .and_then(|x| x.strip_suffix(']'))
+            .or_else(|| index.strip_prefix('.'));
+        let value = index.and_then(|index| match index.split_once(':') {
+            Some((a, b)) => {
+                let a = if a.is_empty() { 0 } else { a.parse().ok()? };
+                let b = if b.is_empty() {
+                    args.len()
+                } else {
+                    b.parse().ok()?
+                };
+                Some(args.get(a..b.min(args.len())).unwrap_or_default().join(" "))
+            }
+            None => Some(
+                args.get(index.parse::<usize>().ok()?)
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+        });
+        match value {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[i..i + 6 + end]),
+        }
+        rest = &tail[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Split `a.com, b.com` of a site block into separated addresses
+fn split_addresses(d: &mut Directive<Caddy>) {
+    let mut addrs = vec![];
+    let mut positions = vec![];
+    let tokens = std::iter::once((&d.name, d.span.name))
+        .chain(d.args.iter().zip(d.span.args.iter().copied()));
+    for (token, pos) in tokens {
+        let mut offset = pos.offset;
+        for addr in token.split(',') {
+            if !addr.is_empty() {
+                addrs.push(addr.to_string());
+                positions.push(Position::new(offset));
+            }
+            offset += addr.len() + 1;
+        }
+    }
+    if addrs.is_empty() {
+        return;
+    }
+    d.name = addrs.remove(0);
+    d.span.name = positions.remove(0);
+    d.args = addrs;
+    d.span.args = positions;
+}
+
+impl<T: Value + AsRef<str>> Render for Directive<Caddy, T> {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        indent(out, depth);
+        let name = self.name.as_ref();
+        // the global options block has no name
+        if !name.is_empty() || !self.args.is_empty() {
+            out.push_str(&quote_literal(name));
+        }
+        for arg in &self.args {
+            out.push(' ');
+            out.push_str(&quote_literal(arg.as_ref()));
+        }
+        if let Some(children) = self.body() {
+            if !name.is_empty() || !self.args.is_empty() {
+                out.push(' ');
+            }
+            out.push_str("{\n");
+            children.render_to(out, depth + 1);
+            indent(out, depth);
+            out.push('}');
+        }
+        out.push('\n');
+    }
+}
+
+/// Quote a name or argument for Caddyfile if necessary, a multi-line value is
+/// written as heredoc
+pub fn quote_literal(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains('\n') {
+        let mut marker = "EOF".to_string();
+        while s.lines().any(|l| l.trim() == marker) {
+            marker.push('_');
+        }
+        return format!("<<{marker}\n{s}\n{marker}").into();
+    }
+    match s {
+        "{" | "}" => format!("\"{s}\"").into(),
+        _ if s.starts_with("<<") => format!("\"{s}\"").into(),
+        _ => quote(s, "`", "#"),
+    }
+}
+
+/// Parse directives from `input`, which is a suffix of `base`, stops before
+/// the `}` closing the block
+fn parse_block<'a>(
+    base: &'a [u8],
+    mut input: &'a [u8],
+) -> IResult<&'a [u8], Vec<Directive<Caddy>>> {
+    let offset = |i: &[u8]| base.len() - i.len();
+    let mut result = vec![];
+    loop {
+        let (start, _) = trivia(input)?;
+        let (mut rest, tok) = tokenizer(start)?;
+        let mut d = Directive::<Caddy>::default();
+        match tok {
+            Token::NewLine => {
+                input = rest;
+                continue;
+            }
+            Token::BlockEnd | Token::Eof => break,
+            // the global options block
+            Token::BlockStart => rest = start,
+            _ => {
+                d.name = tok.value().unwrap_or_default();
+                d.span.name = Position::new(offset(start));
+            }
+        }
+
+        let (end, tok) = loop {
+            let (arg, _) = trivia(rest)?;
+            let (r, tok) = tokenizer(arg)?;
+            match tok.value() {
+                Some(value) => {
+                    d.args.push(value);
+                    d.span.args.push(Position::new(offset(arg)));
+                    rest = r;
+                }
+                None => break (arg, tok),
+            }
+        };
+        input = match tok {
+            Token::BlockStart => {
+                let (rest, _) = tokenizer(end)?;
+                let (rest, res) = parse_block(base, rest)?;
+                d.children.replace(res);
+                let (rest, tok) = tokenizer(rest)?;
+                if tok != Token::BlockEnd {
+                    return Err(unclosed_block(base));
+                }
+                rest
+            }
+            // leave the `}` to the block
+            _ => end,
+        };
+
+        d.span.range = offset(start)..offset(input);
+        result.push(d);
+    }
+    Ok((input, result))
+}
+
+/// Error at the end of input, `input` is any suffix of it
+fn unclosed_block(input: &[u8]) -> nom::Err<Error<&[u8]>> {
+    Error::new(&input[input.len()..], ErrorKind::UnbalancedBrace)
+        .expected(&["}"])
+        .cut()
+}
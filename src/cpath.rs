@@ -2,24 +2,22 @@
 
 use std::ops::Deref;
 
-use crate::lexer::{line_column2, Literal};
+pub use crate::error::IResult;
+use crate::{
+    error::{Error, ErrorKind, ParseError},
+    lexer::{quoted, unquoted, Literal},
+};
 use nom::{
     branch::alt,
-    bytes::complete::{escaped, tag},
-    character::{
-        complete::{char as cchar, multispace0, none_of},
-        streaming::anychar,
-    },
-    combinator::{eof, fail, map, map_opt, map_res, opt, value},
-    error::{context, VerboseError},
-    number::complete::be_u8,
+    bytes::complete::tag,
+    character::complete::multispace0,
+    combinator::{cut, eof, fail, map, map_opt, map_res, opt, peek, value},
+    error::context,
     sequence::{delimited, tuple},
     Parser,
 };
 use regex::Regex;
 
-pub type IResult<I, O> = nom::IResult<I, O, VerboseError<I>>;
-
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Token<'a> {
     Slash,
@@ -41,7 +39,7 @@ pub enum Op {
 #[repr(transparent)]
 pub struct CPathBuf(pub Vec<Item>);
 
-impl<'a> Deref for CPathBuf {
+impl Deref for CPathBuf {
     type Target = CPath;
 
     fn deref(&self) -> &Self::Target {
@@ -50,21 +48,10 @@ impl<'a> Deref for CPathBuf {
 }
 
 impl CPathBuf {
-    pub fn parse(path: &str) -> anyhow::Result<Self> {
-        let res = parse_cpath(path.as_ref()).map_err(|err| {
-            err.map(|e| {
-                let errs = e
-                    .errors
-                    .iter()
-                    .map(|(i, code)| {
-                        let ((l, c), pos) = line_column2(path.as_ref(), i).unwrap();
-                        format!("0x{pos:x}({l}:{c}) err: {:?}", code)
-                    })
-                    .collect::<Vec<_>>();
-                anyhow::anyhow!("{}", errs.join("\n"))
-            })
-        })?;
-        Ok(res.1)
+    pub fn parse(path: &str) -> Result<Self, ParseError> {
+        let input = path.as_bytes();
+        let (_, res) = parse_cpath(input).map_err(|err| ParseError::from_nom(input, err))?;
+        Ok(res)
     }
 }
 
@@ -81,11 +68,11 @@ impl Deref for CPath {
 }
 
 impl CPath {
-    pub fn new<'a>(items: &'a [Item]) -> &'a Self {
+    pub fn new(items: &[Item]) -> &Self {
         unsafe { &*(items as *const _ as *const Self) }
     }
 
-    pub fn peek<'a>(&'a self) -> Option<(&'a Item, &'a Self, bool)> {
+    pub fn peek(&self) -> Option<(&Item, &Self, bool)> {
         let (mut first, mut rest) = self.0.split_first()?;
         let mut anylevel = false;
         while first.filter.any_level() {
@@ -118,6 +105,17 @@ impl Filter {
     }
 }
 
+/// Condition inside brackets, evaluated against the directive matched by the
+/// item's filter.
+///
+/// A `name` made only of digits refers to the argument at that (0-based) index,
+/// any other `name` refers to the child directives with that name (compared
+/// case-insensitively), whose arguments are tested one by one.
+///
+/// - `[name]`: the argument exists, or a child whose name matches the regex exists
+/// - `[/item]`: a child matching `item` (filter and condition) exists
+/// - `[name=value]`: the argument, or any argument of a `name` child, equals `value`
+/// - `[name~regex]`: the argument, or any argument of a `name` child, matches `regex`
 #[derive(Debug)]
 pub enum Cond {
     Exists(Regex),
@@ -126,46 +124,33 @@ pub enum Cond {
     Match { name: String, regex: Regex },
 }
 
-fn literal(input: &[u8]) -> IResult<&[u8], Token> {
-    let (_, mut first) = be_u8(input)?;
-
-    let (input, raw) = match first {
-        b'"' => map_res(
-            // for empty string
-            map(
-                delimited(
-                    cchar(first as _),
-                    opt(escaped(none_of(r#"\""#), '\\', anychar)),
-                    cchar(first as _),
-                ),
-                Option::unwrap_or_default,
-            ),
-            std::str::from_utf8,
-        )(input),
-        b'\'' => map_res(
-            // for empty string
-            map(
-                delimited(
-                    cchar(first as _),
-                    opt(escaped(none_of(r#"\'"#), '\\', anychar)),
-                    cchar(first as _),
-                ),
-                Option::unwrap_or_default,
-            ),
-            std::str::from_utf8,
-        )(input),
-        _ => {
-            first = 0;
-            map_res(
-                escaped(none_of(" \t\r\n'\"\\[]/=~"), '\\', anychar),
-                std::str::from_utf8,
-            )(input)
-        }
+fn literal(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (rest, lit) = match input.first() {
+        Some(b'"' | b'\'') => quoted(input),
+        _ => unquoted(input, " \t\r\n'\"\\[]/=~"),
     }?;
-    Ok((input, Token::Literal(Literal { raw, quote: first })))
+    Ok((rest, Token::Literal(lit)))
+}
+
+impl Token<'_> {
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Slash => "/",
+            Self::DoubleSlash => "//",
+            Self::LeftBracket => "[",
+            Self::RightBracket => "]",
+            Self::Eof => "end of input",
+            Self::Operator(_) => "operator",
+            Self::Literal(_) => "literal",
+        }
+    }
+}
+
+fn regex<'a>(at: &'a [u8], lit: Literal) -> Result<Regex, nom::Err<Error<&'a [u8]>>> {
+    Regex::new(&String::from(lit)).map_err(|_| Error::new(at, ErrorKind::InvalidRegex).cut())
 }
 
-pub fn token(input: &[u8]) -> IResult<&[u8], Token> {
+pub fn token(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     use Token::*;
 
     map(
@@ -195,16 +180,21 @@ fn expect_map<'a, T: 'a>(
 }
 
 fn expect<'a>(tk: Token<'a>) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], ()> + 'a {
-    context(
-        "expect token",
-        map_res(token, move |tok| {
-            if tok == tk {
-                Ok(())
+    move |input| {
+        let (start, _) = multispace0(input)?;
+        let (rest, tok) = token(start)?;
+        if tok == tk {
+            Ok((rest, ()))
+        } else {
+            let kind = if tok == Token::Eof {
+                ErrorKind::UnexpectedEof
             } else {
-                Err(nom::error::ErrorKind::Fail)
-            }
-        }),
-    )
+                ErrorKind::UnexpectedToken
+            };
+            let err = Error::new(start, kind).expected(&[tk.describe()]);
+            Err(nom::Err::Error(err))
+        }
+    }
 }
 
 fn expect_literal<'a>() -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], Literal<'a>> + 'a {
@@ -231,24 +221,30 @@ fn parse_cond(input: &[u8]) -> IResult<&[u8], Cond> {
     fn parser(input: &[u8]) -> IResult<&[u8], Cond> {
         let (rest, tok) = token(input)?;
         match tok {
-            Token::Slash => map(parse_item, |item| Cond::ChildExists(item))(rest),
-            Token::Literal(lit) => alt((
-                tuple((expect_op(), expect_literal())).map(|(op, val)| {
-                    let name = lit.to_string();
-                    match op {
-                        Op::Equal => Cond::Equal {
-                            name,
-                            value: val.to_string(),
-                        },
-                        Op::Match => Cond::Match {
-                            name,
-                            regex: Regex::new(&val.to_string()).unwrap(),
-                        },
+            Token::Slash => map(parse_item, Cond::ChildExists)(rest),
+            Token::Literal(lit) => {
+                let name = lit.to_string();
+                match expect_op()(rest) {
+                    Ok((start, op)) => {
+                        let (end, val) = expect_literal()(start)?;
+                        let cond = match op {
+                            Op::Equal => Cond::Equal {
+                                name,
+                                value: val.to_string(),
+                            },
+                            Op::Match => Cond::Match {
+                                name,
+                                regex: regex(start, val)?,
+                            },
+                        };
+                        return Ok((end, cond));
                     }
-                }),
-                expect(Token::RightBracket)
-                    .map(|_| Cond::Exists(Regex::new(&lit.to_string()).unwrap())),
-            ))(rest),
+                    Err(nom::Err::Error(_)) => {}
+                    Err(err) => return Err(err),
+                }
+                let (rest, _) = peek(expect(Token::RightBracket))(rest)?;
+                Ok((rest, Cond::Exists(regex(input, lit)?)))
+            }
             _ => fail(input),
         }
     }
@@ -257,16 +253,17 @@ fn parse_cond(input: &[u8]) -> IResult<&[u8], Cond> {
         "parse cond",
         delimited(
             expect(Token::LeftBracket),
-            parser,
-            expect(Token::RightBracket),
+            cut(parser),
+            cut(expect(Token::RightBracket)),
         ),
     )(input)
 }
 
 fn parse_item(input: &[u8]) -> IResult<&[u8], Item> {
-    let parse_filter = map(expect_literal(), |lit| {
-        Box::new(Filter::Re(Regex::new(&lit.to_string()).unwrap()))
-    });
+    let parse_filter = |input| {
+        let (rest, lit) = expect_literal()(input)?;
+        Ok((rest, Box::new(Filter::Re(regex(input, lit)?))))
+    };
     context(
         "parse item",
         map(tuple((parse_filter, opt(parse_cond))), |(filter, cond)| {
@@ -287,7 +284,7 @@ fn parse_cpath(mut input: &[u8]) -> IResult<&[u8], CPathBuf> {
             })
         }),
         map(tuple((expect(Token::Slash), opt(parse_item))), |x| x.1),
-        parse_item.map(|x| Some(x)),
+        parse_item.map(Some),
         expect(Token::Eof).map(|_| None),
     ));
 
@@ -0,0 +1,222 @@
+//! Lossless concrete syntax tree
+//!
+//! Unlike [`Directive`], a [`Cst`] keeps comments, blank lines, indentation and the
+//! original quoting of every token, so writing it back with [`ToString`] reproduces
+//! the input byte for byte, and a parse-modify-write cycle only changes the tokens
+//! that were touched.
+
+use std::{borrow::Cow, fmt, marker::PhantomData};
+
+use crate::{
+    ast::{Directive, RawBlock, Value},
+    lexer::{Escapes, Literal},
+};
+
+/// Scheme which can be parsed into a [`Cst`]
+pub trait CstScheme: Sized {
+    fn parse_cst(input: &[u8]) -> anyhow::Result<Cst<Self>>;
+
+    /// Quote a value if necessary, so that it's read back as one literal
+    fn quote(s: &str) -> Cow<'_, str>;
+
+    /// Escape rules to get the values of the tokens
+    const ESCAPES: Escapes = Escapes::Common;
+}
+
+/// Root of a parsed file
+pub type Cst<S> = Block<S>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Token {
+    /// Whitespaces, comments and punctuations before the token
+    pub leading: String,
+    /// Token text as written, including quotes
+    pub raw: String,
+}
+
+impl Token {
+    pub fn new(leading: impl Into<String>, raw: impl Into<String>) -> Self {
+        Self {
+            leading: leading.into(),
+            raw: raw.into(),
+        }
+    }
+
+    /// The literal without its quotes
+    pub fn literal(&self) -> Literal<'_> {
+        match self.raw.as_bytes() {
+            [q @ (b'"' | b'\''), .., e] if q == e && self.raw.len() > 1 => Literal {
+                raw: &self.raw[1..self.raw.len() - 1],
+                quote: *q,
+                ..Default::default()
+            },
+            _ => Literal::from(self.raw.as_str()),
+        }
+    }
+
+    pub fn value<'a, T: Value + From<Literal<'a>>>(&'a self) -> T {
+        self.literal().into()
+    }
+
+    /// Comment lines before the token, including the `#`
+    pub fn comments(&self) -> impl Iterator<Item = &str> {
+        self.leading
+            .lines()
+            .map(str::trim)
+            .filter(|l| l.starts_with('#'))
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.leading)?;
+        f.write_str(&self.raw)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Node<S> {
+    pub name: Token,
+    pub args: Vec<Token>,
+    /// End of the directive head, like `;` or `{` for nginx and `>` for apache sections
+    pub head_end: Token,
+    pub body: Option<Block<S>>,
+    /// Closing of the body, like `}` or `</Directory>`
+    pub tail: Token,
+}
+
+impl<S: CstScheme> Node<S> {
+    pub fn name<'a, T: Value + From<Literal<'a>>>(&'a self) -> T {
+        Self::value(&self.name)
+    }
+
+    pub fn args<'a, T: Value + From<Literal<'a>>>(&'a self) -> Vec<T> {
+        self.args.iter().map(Self::value).collect()
+    }
+
+    /// Value of `token` by the escape rules of the scheme
+    fn value<'a, T: Value + From<Literal<'a>>>(token: &'a Token) -> T {
+        let lit = Literal {
+            escapes: S::ESCAPES,
+            ..token.literal()
+        };
+        lit.into()
+    }
+
+    /// Replace the value of argument `i`, keeping the whitespace before it,
+    /// returns the replaced raw text, `None` if there is no argument `i`
+    pub fn set_arg(&mut self, i: usize, value: &str) -> Option<String> {
+        let arg = self.args.get_mut(i)?;
+        Some(std::mem::replace(
+            &mut arg.raw,
+            S::quote(value).into_owned(),
+        ))
+    }
+
+    pub fn push_arg(&mut self, value: &str) {
+        self.args.push(Token::new(" ", S::quote(value)));
+    }
+
+    /// Remove argument `i`, `None` if there is no such argument
+    pub fn remove_arg(&mut self, i: usize) -> Option<Token> {
+        (i < self.args.len()).then(|| self.args.remove(i))
+    }
+
+    pub fn to_directive<'a, T: Value + From<Literal<'a>>>(&'a self) -> Directive<S, T>
+    where
+        S: Clone + Default,
+    {
+        // a tail without body is a raw block with its closing brace
+        let raw_block = match self.tail.raw.strip_suffix('}') {
+            Some(source) if self.body.is_none() => Some(RawBlock {
+                source: source.into(),
+                ..Default::default()
+            }),
+            _ => None,
+        };
+        Directive {
+            name: self.name(),
+            args: self.args(),
+            children: self.body.as_ref().map(Block::to_directives),
+            raw_block,
+            ..Default::default()
+        }
+    }
+}
+
+impl<S> fmt::Display for Node<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.args {
+            write!(f, "{arg}")?;
+        }
+        write!(f, "{}", self.head_end)?;
+        if let Some(body) = self.body.as_ref() {
+            write!(f, "{body}")?;
+        }
+        write!(f, "{}", self.tail)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Block<S> {
+    pub nodes: Vec<Node<S>>,
+    /// Whitespaces and comments after the last node
+    pub trailing: String,
+    pub(crate) _scheme: PhantomData<S>,
+}
+
+impl<S: CstScheme> Block<S> {
+    pub fn parse(input: &[u8]) -> anyhow::Result<Self> {
+        S::parse_cst(input)
+    }
+
+    pub fn to_directives<'a, T: Value + From<Literal<'a>>>(&'a self) -> Vec<Directive<S, T>>
+    where
+        S: Clone + Default,
+    {
+        self.nodes.iter().map(Node::to_directive).collect()
+    }
+
+    /// Nodes with the name, compared case-insensitively
+    pub fn find<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Node<S>> + 'a {
+        self.nodes
+            .iter()
+            .filter(move |n| n.name.literal().raw.eq_ignore_ascii_case(name))
+    }
+
+    pub fn find_mut<'a>(&'a mut self, name: &'a str) -> impl Iterator<Item = &'a mut Node<S>> + 'a {
+        self.nodes
+            .iter_mut()
+            .filter(move |n| n.name.literal().raw.eq_ignore_ascii_case(name))
+    }
+
+    /// Parse `text` and insert its nodes at `index`, `text` should start with the
+    /// newline and indentation expected before the first node
+    pub fn insert(&mut self, index: usize, text: &str) -> anyhow::Result<()> {
+        let block = Self::parse(text.as_bytes())?;
+        self.nodes.splice(index..index, block.nodes);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Node<S> {
+        self.nodes.remove(index)
+    }
+}
+
+impl<S> fmt::Display for Block<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in &self.nodes {
+            write!(f, "{node}")?;
+        }
+        f.write_str(&self.trailing)
+    }
+}
+
+/// Text of `head` before `rest`, where `rest` is a suffix of `head`
+pub(crate) fn text<'a>(
+    head: &'a [u8],
+    rest: &[u8],
+) -> Result<String, nom::Err<crate::error::Error<&'a [u8]>>> {
+    crate::lexer::utf8(head, head.len() - rest.len()).map(ToString::to_string)
+}
@@ -0,0 +1,261 @@
+//! Structured parse errors
+
+use std::{fmt, path::PathBuf};
+
+use crate::lexer::{LineIndex, Position};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A quoted string without its closing quote
+    UnterminatedQuote,
+    /// A block without its closing `}`, or a `}` without a block to close
+    UnbalancedBrace,
+    /// A section closed by another tag than the one opening it, like
+    /// `<Directory>` closed by `</Files>`
+    MismatchedCloseTag,
+    /// Source text which is not valid UTF-8
+    InvalidUtf8,
+    /// Malformed regex in a query path
+    InvalidRegex,
+    /// Input ended before the syntax was complete
+    UnexpectedEof,
+    /// Any other token which is not valid at the position
+    UnexpectedToken,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UnterminatedQuote => "unterminated quote",
+            Self::UnbalancedBrace => "unbalanced brace",
+            Self::MismatchedCloseTag => "mismatched close tag",
+            Self::InvalidUtf8 => "invalid utf-8",
+            Self::InvalidRegex => "invalid regex",
+            Self::UnexpectedEof => "unexpected end of input",
+            Self::UnexpectedToken => "unexpected token",
+        })
+    }
+}
+
+/// Error of parsing configuration or query path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ErrorKind,
+    pub position: Position,
+    /// Tokens which would be valid at the position
+    pub expected: Vec<String>,
+    /// Token found at the position, `None` at the end of input
+    pub found: Option<String>,
+    /// The source line of the position, and a caret under it
+    pub snippet: String,
+}
+
+impl ParseError {
+    /// Convert a nom error of parsing `source`
+    pub(crate) fn from_nom(source: &[u8], err: nom::Err<Error<&[u8]>>) -> Self {
+        let err = match err {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+            nom::Err::Incomplete(_) => {
+                Error::new(&source[source.len()..], ErrorKind::UnexpectedEof)
+            }
+        };
+        Self::from_error(source, err)
+    }
+
+    /// Convert an error of parsing `source`
+    pub(crate) fn from_error(source: &[u8], err: Error<&[u8]>) -> Self {
+        Self::new(source, source.len() - err.input.len(), err.kind)
+            .expected(err.expected)
+            .found(err.found)
+    }
+
+    /// Error at byte `offset` of `source`, `found` is guessed from the source text
+    pub fn new(source: &[u8], offset: usize, kind: ErrorKind) -> Self {
+        let position = LineIndex::new(source).position(offset);
+        let line_start = offset + 1 - position.column;
+        let line = source[line_start..]
+            .split(|&b| b == b'\n')
+            .next()
+            .unwrap_or_default();
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches('\r');
+
+        let mut snippet = format!("{line}\n");
+        for b in source[line_start..offset].iter() {
+            snippet.push(if *b == b'\t' { '\t' } else { ' ' });
+        }
+        snippet.push('^');
+
+        let rest = &source[offset..];
+        let found = rest.first().map(|&b| {
+            let word = rest.split(|b| b.is_ascii_whitespace()).next();
+            match word.filter(|w| !w.is_empty()) {
+                Some(word) => String::from_utf8_lossy(word).into_owned(),
+                None => (b as char).escape_default().to_string(),
+            }
+        });
+        Self {
+            kind,
+            position,
+            expected: vec![],
+            found,
+            snippet,
+        }
+    }
+
+    pub fn expected(mut self, expected: Vec<String>) -> Self {
+        if !expected.is_empty() {
+            self.expected = expected;
+        }
+        self
+    }
+
+    pub fn found(mut self, found: Option<String>) -> Self {
+        if found.is_some() {
+            self.found = found;
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.kind)?;
+        if !self.expected.is_empty() {
+            write!(f, ", expected {}", self.expected.join(" or "))?;
+        }
+        match self.found.as_ref() {
+            Some(found) => write!(f, ", found `{found}`")?,
+            None => write!(f, ", found end of input")?,
+        }
+        write!(f, "\n{}", self.snippet)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Error of resolving includes, which can be retrieved by `downcast_ref`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncludeError {
+    /// Chain of included files, or `(name)` of caddy snippets, ending with the
+    /// one already being included
+    Cycle(Vec<PathBuf>),
+    /// Maximum depth, and the chain of included files exceeding it
+    TooDeep(usize, Vec<PathBuf>),
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chain = match self {
+            Self::Cycle(chain) => {
+                f.write_str("include cycle: ")?;
+                chain
+            }
+            Self::TooDeep(max, chain) => {
+                write!(f, "includes nested deeper than {max}: ")?;
+                chain
+            }
+        };
+        for (i, path) in chain.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" -> ")?;
+            }
+            write!(f, "{}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+/// Error type of the nom parsers in this crate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error<I> {
+    pub input: I,
+    pub kind: ErrorKind,
+    pub expected: Vec<String>,
+    pub found: Option<String>,
+}
+
+impl<I> Error<I> {
+    pub fn new(input: I, kind: ErrorKind) -> Self {
+        Self {
+            input,
+            kind,
+            expected: vec![],
+            found: None,
+        }
+    }
+
+    pub fn expected(mut self, expected: &[&str]) -> Self {
+        self.expected = expected.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    pub fn found(mut self, found: impl Into<String>) -> Self {
+        self.found = Some(found.into());
+        self
+    }
+
+    /// Make it unrecoverable
+    pub fn cut(self) -> nom::Err<Self> {
+        nom::Err::Failure(self)
+    }
+}
+
+impl<I> nom::error::ParseError<I> for Error<I> {
+    fn from_error_kind(input: I, _kind: nom::error::ErrorKind) -> Self {
+        Self::new(input, ErrorKind::UnexpectedToken)
+    }
+
+    fn append(_input: I, _kind: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> nom::error::ContextError<I> for Error<I> {}
+
+impl<I> nom::error::FromExternalError<I, nom::error::ErrorKind> for Error<I> {
+    fn from_external_error(
+        input: I,
+        _kind: nom::error::ErrorKind,
+        _e: nom::error::ErrorKind,
+    ) -> Self {
+        Self::new(input, ErrorKind::UnexpectedToken)
+    }
+}
+
+impl<I> nom::error::FromExternalError<I, std::str::Utf8Error> for Error<I> {
+    fn from_external_error(
+        input: I,
+        _kind: nom::error::ErrorKind,
+        _e: std::str::Utf8Error,
+    ) -> Self {
+        Self::new(input, ErrorKind::InvalidUtf8)
+    }
+}
+
+impl<I> nom::error::FromExternalError<I, regex::Error> for Error<I> {
+    fn from_external_error(input: I, _kind: nom::error::ErrorKind, _e: regex::Error) -> Self {
+        Self::new(input, ErrorKind::InvalidRegex)
+    }
+}
+
+pub type IResult<I, O> = nom::IResult<I, O, Error<I>>;
+
+/// Errors collected by a recovering parser, `None` to stop at the first error
+pub(crate) type Diagnostics<'a> = Option<Vec<Error<&'a [u8]>>>;
+
+/// Record `err` when recovering, otherwise return it
+pub(crate) fn recover<'a>(
+    diag: &mut Diagnostics<'a>,
+    err: nom::Err<Error<&'a [u8]>>,
+) -> Result<(), nom::Err<Error<&'a [u8]>>> {
+    match (diag, err) {
+        (Some(errors), nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            errors.push(e);
+            Ok(())
+        }
+        (_, err) => Err(err),
+    }
+}
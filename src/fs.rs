@@ -0,0 +1,126 @@
+//! Filesystems the configuration files and their includes are read from
+
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// Where [`Config::parse_in`](crate::ast::Config::parse_in) and the include
+/// resolution read files, see [`IncludeContext::fs`](crate::ast::IncludeContext::fs)
+pub trait ConfigFs {
+    fn read(&self, path: &Path) -> anyhow::Result<Vec<u8>>;
+
+    /// Files matching the glob `pattern`, in alphabetical order
+    fn glob(&self, pattern: &str) -> anyhow::Result<Vec<PathBuf>>;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    /// The path identifying the file of `path`, to detect include cycles
+    fn canonicalize(&self, path: &Path) -> PathBuf {
+        normalize(path)
+    }
+}
+
+/// The filesystem of the OS
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdFs;
+
+impl ConfigFs for StdFs {
+    fn read(&self, path: &Path) -> anyhow::Result<Vec<u8>> {
+        Ok(std::fs::read(path)?)
+    }
+
+    fn glob(&self, pattern: &str) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(glob::glob(pattern)?.flatten().collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn canonicalize(&self, path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| normalize(path))
+    }
+}
+
+/// Files held in memory, like the ones of a container image or a tarball
+///
+/// Paths are compared as written, except `.` and `..` components which are
+/// resolved, like the OS does without symlinks.
+#[derive(Debug, Clone, Default)]
+pub struct MemFs {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace the file `path`
+    pub fn insert(&mut self, path: impl AsRef<Path>, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.files.insert(normalize(path.as_ref()), data.into());
+        self
+    }
+}
+
+impl<P: AsRef<Path>, D: Into<Vec<u8>>> FromIterator<(P, D)> for MemFs {
+    fn from_iter<I: IntoIterator<Item = (P, D)>>(iter: I) -> Self {
+        let mut fs = Self::new();
+        for (path, data) in iter {
+            fs.insert(path, data);
+        }
+        fs
+    }
+}
+
+impl ConfigFs for MemFs {
+    fn read(&self, path: &Path) -> anyhow::Result<Vec<u8>> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .with_context(|| format!("read {path:?}: no such file"))
+    }
+
+    fn glob(&self, pattern: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let pattern = normalize(Path::new(pattern));
+        let pattern = glob::Pattern::new(&pattern.to_string_lossy())?;
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            require_literal_leading_dot: true,
+            ..Default::default()
+        };
+        Ok(self
+            .files
+            .keys()
+            .filter(|path| pattern.matches_path_with(path, options))
+            .cloned()
+            .collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(&normalize(path))
+    }
+}
+
+/// `path` without `.` components, and `..` ones resolved as far as possible
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                }
+                // `/..` is `/`
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => result.push(c),
+            },
+            _ => result.push(c),
+        }
+    }
+    result
+}
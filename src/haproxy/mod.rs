@@ -0,0 +1,49 @@
+//! Nom parser for haproxy configuration
+//!
+//! HAProxy configuration is line based, a section keyword like `frontend` starts a
+//! section which lasts until the next one, the lines between are its children. It's
+//! parsed by the generic [`line`](crate::line) parser, with the escapes of
+//! [`Escapes::Haproxy`] like `\ ` or `\x41`. Environment variables in double
+//! quotes are not expanded.
+
+use crate::{
+    lexer::Escapes,
+    line::{LineScheme, LineSyntax},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Haproxy;
+
+/// Keywords starting a section
+pub const SECTIONS: &[&str] = &[
+    "global",
+    "defaults",
+    "frontend",
+    "backend",
+    "listen",
+    "peers",
+    "resolvers",
+    "userlist",
+    "mailers",
+    "program",
+    "http-errors",
+    "ring",
+    "cache",
+    "fcgi-app",
+    "crt-store",
+    "log-forward",
+    "traces",
+];
+
+impl LineScheme for Haproxy {
+    const SYNTAX: LineSyntax = LineSyntax::new()
+        .comment(b'#', true)
+        .quotes(b"\"'")
+        .sections(SECTIONS)
+        .escapes(Escapes::Haproxy);
+}
+
+/// Quote a name or argument for haproxy configuration if necessary
+pub fn quote_literal(s: &str) -> std::borrow::Cow<'_, str> {
+    Haproxy::SYNTAX.quote(s)
+}
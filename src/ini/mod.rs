@@ -0,0 +1,304 @@
+//! Nom parser for INI style configuration, like systemd units
+//!
+//! A `[Section]` header is a directive named `Section` whose children are the
+//! `key=value` lines till the next header, the value is the only argument, or
+//! no argument at all if it's empty. Lines ended by `\` are joined with a space.
+//! A `\` ending the input is dropped.
+//!
+//! A key may be assigned more than once, [`values`] and [`value`] read them as
+//! systemd does. The drop-in files `<unit>.d/*.conf` are merged into the unit by
+//! [`Config::resolve_include`](crate::ast::Config::resolve_include), their
+//! directives are included by a directive named `<unit>.d` with the argument
+//! `*.conf`, which is kept in each section they extend if
+//! [`IncludeContext::keep_includes`].
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{
+    ast::{Directive, DirectiveTrait, IncludeContext, Value},
+    error::{Error, ErrorKind, IResult, ParseError},
+    lexer::{utf8, LineIndex, Position},
+    render::{indent, Render},
+    utils::*,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Ini;
+
+impl DirectiveTrait<Ini> for Directive<Ini> {
+    fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>> {
+        let (_, mut result) = parse_lines(input).map_err(|err| ParseError::from_nom(input, err))?;
+        let index = LineIndex::new(input);
+        for d in result.iter_mut() {
+            d.locate(&index);
+        }
+        Ok(result)
+    }
+
+    fn resolve_include_inner(
+        mut self,
+        ctx: &mut IncludeContext,
+        out: &mut Vec<Self>,
+    ) -> anyhow::Result<()> {
+        self.resolve_include(ctx)?;
+        out.push(self);
+        Ok(())
+    }
+
+    fn resolve_dropin(
+        root: &mut Self,
+        path: &Path,
+        ctx: &mut IncludeContext,
+    ) -> anyhow::Result<()> {
+        let mut dir = path.as_os_str().to_owned();
+        dir.push(".d");
+        let dir = PathBuf::from(dir);
+        let paths = glob_include(ctx.fs, "*.conf", &dir, ctx.res)?;
+        if paths.is_empty() {
+            return Ok(());
+        }
+        // the drop-in directory stands for the include directive
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        let mut site = Directive::<Ini>::new(name, ["*.conf"]);
+        site.span.path = Some(Arc::from(path));
+        let mut dropin = vec![];
+        site.resolve_files(paths, ctx, &mut dropin)?;
+        merge(root, dropin);
+        Ok(())
+    }
+}
+
+/// Append the sections of a drop-in to the last section of the same name, a
+/// kept drop-in is split in transparent containers, one in each section
+fn merge(root: &mut Directive<Ini>, dropin: Vec<Directive<Ini>>) {
+    let children = root.children.get_or_insert_with(Vec::new);
+    for d in dropin {
+        if !d.transparent {
+            match section(children, &d) {
+                Some(s) => s.children_mut().extend(d.children.into_iter().flatten()),
+                None => children.push(d),
+            }
+            continue;
+        }
+        for c in d.children.iter().flatten() {
+            match section(children, c) {
+                Some(s) => s.children_mut().push(Directive {
+                    children: c.children.clone(),
+                    ..d.clone()
+                }),
+                None => children.push(Directive {
+                    children: Some(vec![c.clone()]),
+                    ..d.clone()
+                }),
+            }
+        }
+    }
+}
+
+/// The last section named like the section `d`
+fn section<'a>(
+    children: &'a mut [Directive<Ini>],
+    d: &Directive<Ini>,
+) -> Option<&'a mut Directive<Ini>> {
+    d.children.as_ref()?;
+    children
+        .iter_mut()
+        .rev()
+        .find(|s| s.children.is_some() && !s.transparent && s.name == d.name)
+}
+
+/// Values assigned to `key` in the sections named `section`, in order; an empty
+/// assignment resets the list, like systemd does for list settings
+pub fn values<'a>(dirs: &'a [Directive<Ini>], section: &str, key: &str) -> Vec<&'a str> {
+    let mut result = vec![];
+    let sections = visible(dirs).filter(|s| s.name == section && s.children.is_some());
+    for d in sections.flat_map(|s| visible(s.children.as_deref().unwrap_or_default())) {
+        if d.name != key {
+            continue;
+        }
+        match d.args.first() {
+            Some(value) => result.push(value.as_str()),
+            None => result.clear(),
+        }
+    }
+    result
+}
+
+/// The directives, with the ones of a kept drop-in in place of it
+fn visible(dirs: &[Directive<Ini>]) -> Box<dyn Iterator<Item = &Directive<Ini>> + '_> {
+    Box::new(dirs.iter().flat_map(|d| match d.transparent {
+        true => visible(d.children.as_deref().unwrap_or_default()),
+        false => Box::new(std::iter::once(d)),
+    }))
+}
+
+/// The last value assigned to `key` in the sections named `section`, `None` if
+/// it's never assigned or reset by an empty assignment
+pub fn value<'a>(dirs: &'a [Directive<Ini>], section: &str, key: &str) -> Option<&'a str> {
+    values(dirs, section, key).pop()
+}
+
+impl<T: Value + AsRef<str>> Render for Directive<Ini, T> {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        // a kept drop-in stays in its own file
+        if self.transparent {
+            return;
+        }
+        indent(out, depth);
+        if let Some(children) = self.body() {
+            out.push('[');
+            out.push_str(self.name.as_ref());
+            out.push_str("]\n");
+            children.render_to(out, depth);
+            return;
+        }
+        out.push_str(self.name.as_ref());
+        out.push('=');
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            out.push_str(arg.as_ref());
+        }
+        out.push('\n');
+    }
+}
+
+fn is_space(b: &u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\r')
+}
+
+fn skip_space(input: &[u8]) -> &[u8] {
+    let len = input.iter().take_while(|b| is_space(b)).count();
+    &input[len..]
+}
+
+/// Length of the line at the start of `input`, excluding the newline
+fn line_len(input: &[u8]) -> usize {
+    input
+        .iter()
+        .position(|&b| b == b'\n')
+        .unwrap_or(input.len())
+}
+
+/// The input after the comment line at its start, if any
+fn comment_line(input: &[u8]) -> Option<&[u8]> {
+    let start = skip_space(input);
+    match start.first() {
+        Some(b'#' | b';') => {
+            let len = line_len(start);
+            Some(&start[(len + 1).min(start.len())..])
+        }
+        _ => None,
+    }
+}
+
+/// Value till the end of line, joining the lines ended by `\` and skipping the
+/// comment lines among them
+fn parse_value(input: &[u8]) -> IResult<&[u8], String> {
+    let mut value = String::new();
+    let mut rest = input;
+    loop {
+        let line = utf8(rest, line_len(rest))?;
+        rest = &rest[line.len()..];
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match line.strip_suffix('\\') {
+            // a `\` at the end of input continues into nothing
+            Some(line) if rest.is_empty() => {
+                value.push_str(line);
+                break;
+            }
+            Some(line) => {
+                value.push_str(line);
+                value.push(' ');
+                rest = &rest[1..];
+                while let Some(next) = comment_line(rest) {
+                    rest = next;
+                }
+            }
+            None => {
+                value.push_str(line);
+                break;
+            }
+        }
+    }
+    Ok((rest, value.trim().into()))
+}
+
+fn parse_lines(base: &[u8]) -> IResult<&[u8], Vec<Directive<Ini>>> {
+    let offset = |i: &[u8]| base.len() - i.len();
+    let mut result = vec![];
+    let mut section: Option<Directive<Ini>> = None;
+    let mut input = base;
+    loop {
+        if let Some(rest) = comment_line(input) {
+            input = rest;
+            continue;
+        }
+        let start = skip_space(input);
+        let len = line_len(start);
+        match start.first() {
+            None => break,
+            Some(b'\n') => {
+                input = &start[1..];
+                continue;
+            }
+            Some(b'[') => {
+                let Some(close) = start[..len].iter().position(|&b| b == b']') else {
+                    let err =
+                        Error::new(&start[len..], ErrorKind::UnexpectedToken).expected(&["]"]);
+                    return Err(err.cut());
+                };
+                let rest = skip_space(&start[close + 1..]);
+                if !matches!(rest.first(), None | Some(b'\n')) {
+                    let err = Error::new(rest, ErrorKind::UnexpectedToken).expected(&["newline"]);
+                    return Err(err.cut());
+                }
+                let mut d = Directive::<Ini> {
+                    name: utf8(&start[1..], close - 1)?.trim().into(),
+                    children: Some(vec![]),
+                    ..Default::default()
+                };
+                d.span.name = Position::new(offset(start));
+                d.span.range = offset(start)..offset(&start[close + 1..]);
+                result.extend(section.replace(d));
+                input = rest;
+            }
+            Some(_) => {
+                let Some(eq) = start[..len].iter().position(|&b| b == b'=') else {
+                    let err =
+                        Error::new(&start[len..], ErrorKind::UnexpectedToken).expected(&["="]);
+                    return Err(err.cut());
+                };
+                let key = utf8(start, eq)?.trim_end();
+                if key.is_empty() {
+                    let err = Error::new(start, ErrorKind::UnexpectedToken).expected(&["key"]);
+                    return Err(err.cut());
+                }
+                let mut d = Directive::<Ini> {
+                    name: key.into(),
+                    ..Default::default()
+                };
+                d.span.name = Position::new(offset(start));
+                let arg = skip_space(&start[eq + 1..]);
+                let (rest, value) = parse_value(arg)?;
+                if !value.is_empty() {
+                    d.args.push(value);
+                    d.span.args.push(Position::new(offset(arg)));
+                }
+                d.span.range = offset(start)..offset(rest);
+                match section.as_mut() {
+                    Some(s) => {
+                        s.span.range.end = d.span.range.end;
+                        s.children.get_or_insert_with(Vec::new).push(d);
+                    }
+                    None => result.push(d),
+                }
+                input = rest;
+            }
+        }
+    }
+    result.extend(section);
+    Ok((input, result))
+}
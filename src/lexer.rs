@@ -1,38 +1,283 @@
 //! Common lexer utils
 
+use std::borrow::Cow;
+
+use nom::{
+    bytes::complete::escaped,
+    character::{
+        complete::{char as cchar, none_of},
+        streaming::anychar,
+    },
+    combinator::opt,
+    sequence::delimited,
+};
+
+use crate::error::{Error, ErrorKind, IResult};
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct Literal<'a> {
     pub raw: &'a str,
     pub quote: u8,
+    /// Rules of the scheme to get the value of `raw`
+    pub escapes: Escapes,
 }
 
 impl<'a> From<&'a str> for Literal<'a> {
     fn from(raw: &'a str) -> Self {
-        Self { raw, quote: 0 }
+        Self {
+            raw,
+            quote: 0,
+            escapes: Escapes::Common,
+        }
+    }
+}
+
+/// Escape rules of a scheme, any backslash not starting an escape is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Escapes {
+    /// `\\`, `\` before the enclosing quote and `\` before a newline
+    #[default]
+    Common,
+    /// `\\` and `\` before the enclosing quote like httpd, a `\` ending the
+    /// line, even before trailing whitespaces, joins the next one
+    Apache,
+    /// `\"`, `\'`, `\\`, `\t`, `\r` and `\n`, quoted or not, like nginx does
+    Nginx,
+    /// `\` escapes any char but in single quotes, `\t`, `\r` and `\n` are
+    /// control chars and `\xHH` is the char U+00HH, like haproxy does
+    Haproxy,
+    /// No escape, backslashes are kept as is
+    Raw,
+}
+
+impl Escapes {
+    /// The char escaped by `\c`, `quote` is the enclosing one
+    pub(crate) fn unescape(self, c: char, quote: Option<char>) -> Option<char> {
+        match self {
+            Self::Common if c == '\\' || c == '\n' || Some(c) == quote => Some(c),
+            Self::Apache if c == '\\' || Some(c) == quote => Some(c),
+            Self::Nginx => match c {
+                '"' | '\'' | '\\' => Some(c),
+                't' => Some('\t'),
+                'r' => Some('\r'),
+                'n' => Some('\n'),
+                _ => None,
+            },
+            Self::Haproxy if quote != Some('\'') => match c {
+                't' => Some('\t'),
+                'r' => Some('\r'),
+                'n' => Some('\n'),
+                _ => Some(c),
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether `s` would be read back as is without quotes, as far as escapes
+    /// are concerned
+    pub(crate) fn is_plain(self, s: &str) -> bool {
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\'
+                && chars
+                    .next()
+                    .is_none_or(|c| self.unescape(c, None).is_some())
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Write `c` in a double-quoted literal
+    pub(crate) fn escape(self, c: char, out: &mut String) {
+        match (self, c) {
+            (Self::Raw, _) => out.push(c),
+            (_, '\\' | '"') => {
+                out.push('\\');
+                out.push(c);
+            }
+            (Self::Common, '\n') => out.push_str("\\\n"),
+            (Self::Nginx | Self::Haproxy, '\n') => out.push_str("\\n"),
+            (Self::Nginx | Self::Haproxy, '\r') => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// The raw text, escapes are kept as in the source
+impl AsRef<str> for Literal<'_> {
+    fn as_ref(&self) -> &str {
+        self.raw
     }
 }
 
 impl<'a> std::fmt::Display for Literal<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", Into::<String>::into(*self))
+        f.write_str(&self.unescape())
     }
 }
 
-// TODO: performance
+impl<'a> Literal<'a> {
+    /// The value with escapes resolved by the [`Escapes`] rules, borrowing `raw`
+    /// if there is no escape
+    pub fn unescape(&self) -> Cow<'a, str> {
+        if self.quote == b'`' || !self.raw.contains('\\') {
+            // raw string without escapes
+            return Cow::Borrowed(self.raw);
+        }
+        let quote = match self.quote {
+            q @ (b'"' | b'\'') => Some(char::from(q)),
+            _ => None,
+        };
+        let mut s = String::with_capacity(self.raw.len());
+        let mut rest = self.raw;
+        while let Some(i) = rest.find('\\') {
+            s.push_str(&rest[..i]);
+            if self.escapes == Escapes::Apache {
+                if let Some(len) = continuation(&rest.as_bytes()[i..]) {
+                    rest = &rest[i + len..];
+                    continue;
+                }
+            }
+            if self.escapes == Escapes::Haproxy && quote != Some('\'') {
+                let hex = rest.get(i + 1..i + 4).and_then(|x| x.strip_prefix('x'));
+                if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    s.push(char::from(b));
+                    rest = &rest[i + 4..];
+                    continue;
+                }
+            }
+            let next = rest[i + 1..].chars().next();
+            match next.and_then(|c| self.escapes.unescape(c, quote).map(|v| (c, v))) {
+                Some((c, value)) => {
+                    s.push(value);
+                    rest = &rest[i + 1 + c.len_utf8()..];
+                }
+                _ => {
+                    s.push('\\');
+                    rest = &rest[i + 1..];
+                }
+            }
+        }
+        s.push_str(rest);
+        Cow::Owned(s)
+    }
+}
 
 impl<'a> From<Literal<'a>> for String {
     fn from(value: Literal<'a>) -> Self {
-        let Literal { raw, quote } = value;
-        let mut s = raw.replace(r#"\\"#, "\\");
-        if quote == b'"' {
-            s = s.replace(r#"\""#, "\"");
-        } else if quote == b'\'' {
-            s = s.replace(r#"\'"#, "'");
+        value.unescape().into_owned()
+    }
+}
+
+/// Position in the source text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    /// Byte offset
+    pub offset: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, in bytes
+    pub column: usize,
+}
+
+impl Position {
+    /// Position at the byte offset, line and column are filled by [`LineIndex::locate`]
+    pub fn new(offset: usize) -> Self {
+        Self {
+            offset,
+            ..Default::default()
         }
-        s.replace("\\\n", "\n")
     }
 }
 
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Start offsets of the lines in a text, to map byte offsets to line and column
+#[derive(Debug, Clone)]
+pub struct LineIndex(Vec<usize>);
+
+impl LineIndex {
+    pub fn new(data: &[u8]) -> Self {
+        let starts = std::iter::once(0)
+            .chain(
+                data.iter()
+                    .enumerate()
+                    .filter(|x| *x.1 == b'\n')
+                    .map(|x| x.0 + 1),
+            )
+            .collect();
+        Self(starts)
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.0.partition_point(|&start| start <= offset);
+        Position {
+            offset,
+            line,
+            column: offset - self.0[line - 1] + 1,
+        }
+    }
+
+    /// Fill line and column of the position from its offset
+    pub fn locate(&self, pos: &mut Position) {
+        *pos = self.position(pos.offset);
+    }
+}
+
+/// Quoted literal, `input` starts with the quote char
+pub(crate) fn quoted(input: &[u8]) -> IResult<&[u8], Literal<'_>> {
+    let quote = input[0];
+    let normal = if quote == b'"' { "\\\"" } else { "\\'" };
+    let res: IResult<_, _> = delimited(
+        cchar(quote as char),
+        // for empty string
+        opt(escaped(none_of(normal), '\\', anychar)),
+        cchar(quote as char),
+    )(input);
+    let (rest, raw) = res.map_err(|_| Error::new(input, ErrorKind::UnterminatedQuote).cut())?;
+    let raw = utf8(&input[1..], raw.map_or(0, <[u8]>::len))?;
+    Ok((
+        rest,
+        Literal {
+            raw,
+            quote,
+            ..Default::default()
+        },
+    ))
+}
+
+/// Unquoted literal, ended by any char of `special`, `\` escapes the next char
+pub(crate) fn unquoted<'a>(input: &'a [u8], special: &str) -> IResult<&'a [u8], Literal<'a>> {
+    let (rest, raw) = escaped(none_of(special), '\\', anychar)(input)?;
+    Ok((rest, Literal::from(utf8(input, raw.len())?)))
+}
+
+/// Length of the line continuation `\`, trailing spaces or tabs and newline
+/// starting `input`
+pub(crate) fn continuation(input: &[u8]) -> Option<usize> {
+    let rest = input.strip_prefix(b"\\")?;
+    let blank = rest
+        .iter()
+        .take_while(|&&b| b == b' ' || b == b'\t')
+        .count();
+    let rest = &rest[blank..];
+    let rest = rest.strip_prefix(b"\r").unwrap_or(rest);
+    rest.strip_prefix(b"\n")
+        .map(|rest| input.len() - rest.len())
+}
+
+/// The first `len` bytes of `input` as str, the error points into `input`
+pub(crate) fn utf8(input: &[u8], len: usize) -> Result<&str, nom::Err<Error<&[u8]>>> {
+    std::str::from_utf8(&input[..len])
+        .map_err(|e| Error::new(&input[e.valid_up_to()..], ErrorKind::InvalidUtf8).cut())
+}
+
 pub fn line_column(data: &[u8], pos: usize) -> (usize, usize) {
     let mut ln = 1;
     for line in data.split(|&b| b == b'\n') {
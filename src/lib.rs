@@ -2,7 +2,19 @@
 
 pub mod apache;
 pub mod ast;
+pub mod caddy;
 pub mod cpath;
+pub mod cst;
+pub mod error;
+pub mod fs;
+pub mod haproxy;
+pub mod ini;
 pub mod lexer;
+pub mod lighttpd;
+pub mod line;
 pub mod nginx;
+pub mod redis;
+pub mod render;
+pub mod squid;
+pub mod ssh;
 pub mod utils;
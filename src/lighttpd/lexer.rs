@@ -0,0 +1,133 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till, take_while1},
+    character::{complete::multispace0, is_newline},
+    combinator::{map, map_res, opt, value},
+    sequence::tuple,
+};
+
+pub use crate::error::IResult;
+use crate::{
+    error::{Error, ErrorKind},
+    lexer::{quoted, Literal},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Token<'a> {
+    BlockStart,
+    BlockEnd,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    /// `=>` in arrays
+    Arrow,
+    /// `+` concatenation
+    Plus,
+    /// Assignment like `=`, `+=`, or comparison like `==`, `=~`
+    Operator(&'a str),
+    Eof,
+    /// Quoted string
+    Literal(Literal<'a>),
+    /// Unquoted key, variable, number or condition name like `$HTTP`
+    Word(&'a str),
+}
+
+impl<'a> Token<'a> {
+    pub fn literal(&self) -> Option<Literal<'a>> {
+        match self {
+            Self::Literal(l) => Some(*l),
+            Self::Word(w) => Some(Literal::from(*w)),
+            _ => None,
+        }
+    }
+}
+
+fn comment(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    map(
+        tuple((
+            tag("#"),
+            take_till(is_newline),
+            opt(tag(b"\r")),
+            opt(tag(b"\n")),
+        )),
+        |x| x.1,
+    )(input)
+}
+
+fn punct(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    alt((
+        value(Token::BlockStart, tag(b"{")),
+        value(Token::BlockEnd, tag(b"}")),
+        value(Token::LeftParen, tag(b"(")),
+        value(Token::RightParen, tag(b")")),
+        value(Token::LeftBracket, tag(b"[")),
+        value(Token::RightBracket, tag(b"]")),
+        value(Token::Comma, tag(b",")),
+        value(Token::Arrow, tag(b"=>")),
+    ))(input)
+}
+
+fn operator(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    map_res(
+        alt((
+            tag(b"=="),
+            tag(b"!="),
+            tag(b"=~"),
+            tag(b"!~"),
+            tag(b"=^"),
+            tag(b"=$"),
+            tag(b"+="),
+            tag(b":="),
+            tag(b"="),
+        )),
+        |op| std::str::from_utf8(op).map(Token::Operator),
+    )(input)
+}
+
+fn word(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    map_res(
+        take_while1(|b: u8| b.is_ascii_alphanumeric() || b"_.-$:".contains(&b)),
+        |w| std::str::from_utf8(w).map(Token::Word),
+    )(input)
+}
+
+fn literal(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    if input.first() != Some(&b'"') {
+        return Err(nom::Err::Error(Error::new(
+            input,
+            ErrorKind::UnexpectedToken,
+        )));
+    }
+    let (rest, lit) = quoted(input)?;
+    Ok((rest, Token::Literal(lit)))
+}
+
+/// Skip whitespaces and comments, returns the skipped text
+pub fn trivia(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let mut rest = input;
+    loop {
+        let (r, (_, cmt)) = tuple((multispace0, opt(comment)))(rest)?;
+        rest = r;
+        if cmt.is_none() {
+            break;
+        }
+    }
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+pub fn tokenizer(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (input, _) = trivia(input)?;
+    if input.is_empty() {
+        return Ok((input, Token::Eof));
+    }
+
+    alt((
+        punct,
+        operator,
+        value(Token::Plus, tag(b"+")),
+        word,
+        literal,
+    ))(input)
+}
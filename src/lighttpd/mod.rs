@@ -0,0 +1,393 @@
+//! Nom parser for lighttpd configuration
+//!
+//! An assignment `key op value` is a directive named `key` whose first argument is
+//! the operator `=`, `+=` or `:=`, followed by the value. A string keeps its
+//! quotes to tell it from a number or a variable, see [`unquote`], and a
+//! concatenation `var.dir + "/x"` is the arguments `var.dir` and `"/x"`. An array is
+//! the children, an element `"a" => value` is named `a` with `=>` and the value as
+//! arguments, an element without key has an empty name and the value as arguments.
+//! The value of an element may be an array again.
+//!
+//! A conditional `$HTTP["host"] == "x" { ... }` is a directive named `$HTTP["host"]`
+//! with the operator and the pattern without quotes as arguments, and the statements inside as
+//! children. `else`, `elseif` and `else if` are all named `else`, followed by their
+//! condition if any. `include_shell` is kept as a directive, the command is never run.
+
+pub mod lexer;
+
+use std::borrow::Cow;
+
+use anyhow::Context;
+
+use crate::{
+    ast::{Directive, DirectiveTrait, IncludeContext, Value},
+    error::{Error, ErrorKind, ParseError},
+    lexer::{utf8, Escapes, LineIndex, Literal, Position},
+    render::{indent, Render},
+    utils::*,
+};
+
+use self::lexer::*;
+
+#[derive(Debug, Clone, Default)]
+pub struct Lighttpd;
+
+impl DirectiveTrait<Lighttpd> for Directive<Lighttpd> {
+    fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>> {
+        let (rest, mut result) =
+            parse_block(input, input).map_err(|err| ParseError::from_nom(input, err))?;
+        let (rest, _) = trivia(rest).map_err(|err| ParseError::from_nom(input, err))?;
+        if !rest.is_empty() {
+            let offset = input.len() - rest.len();
+            return Err(ParseError::new(input, offset, ErrorKind::UnbalancedBrace).into());
+        }
+        let index = LineIndex::new(input);
+        for d in result.iter_mut() {
+            d.locate(&index);
+        }
+        Ok(result)
+    }
+
+    fn resolve_include_inner(
+        mut self,
+        ctx: &mut IncludeContext,
+        out: &mut Vec<Self>,
+    ) -> anyhow::Result<()> {
+        if self.name == "include" {
+            let pattern = self
+                .args
+                .first()
+                .context("include directive expect one arg")?;
+            let paths = glob_include(ctx.fs, &unquote(pattern), &ctx.root, ctx.res)?;
+            self.resolve_files(paths, ctx, out)?;
+        } else {
+            // the children of an assignment are array elements
+            if !is_assignment(&self) {
+                self.resolve_include(ctx)?;
+            }
+            out.push(self);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Value + AsRef<str>> Render for Directive<Lighttpd, T> {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        indent(out, depth);
+        let name = self.name.as_ref();
+        out.push_str(name);
+        if name.starts_with('$') || name == "else" {
+            // the pattern is the last argument of a condition
+            for (i, arg) in self.args.iter().enumerate() {
+                out.push(' ');
+                if i + 1 == self.args.len() {
+                    out.push_str(&quote_literal(arg.as_ref()));
+                } else {
+                    out.push_str(arg.as_ref());
+                }
+            }
+            out.push_str(" {\n");
+            if let Some(children) = self.body() {
+                children.render_to(out, depth + 1);
+            }
+            indent(out, depth);
+            out.push_str("}\n");
+            return;
+        }
+        let mut value = &self.args[..];
+        if is_assignment(self) {
+            out.push(' ');
+            out.push_str(self.args[0].as_ref());
+            value = &value[1..];
+        }
+        out.push(' ');
+        render_value(self, value, out, depth);
+        out.push('\n');
+    }
+}
+
+fn is_assignment<T: Value + AsRef<str>>(d: &Directive<Lighttpd, T>) -> bool {
+    matches!(d.args.first().map(AsRef::as_ref), Some("=" | "+=" | ":="))
+}
+
+/// The array of `d`, or the `terms` of a concatenation
+fn render_value<T: Value + AsRef<str>>(
+    d: &Directive<Lighttpd, T>,
+    terms: &[T],
+    out: &mut String,
+    depth: usize,
+) {
+    let Some(elements) = d.body() else {
+        for (i, term) in terms.iter().enumerate() {
+            if i > 0 {
+                out.push_str(" + ");
+            }
+            out.push_str(term.as_ref());
+        }
+        return;
+    };
+    if elements.is_empty() {
+        out.push_str("()");
+        return;
+    }
+    out.push_str("(\n");
+    for e in elements {
+        indent(out, depth + 1);
+        let mut terms = &e.args[..];
+        if terms.first().is_some_and(|a| a.as_ref() == "=>") {
+            out.push_str(&quote_literal(e.name.as_ref()));
+            out.push_str(" => ");
+            terms = &terms[1..];
+        }
+        render_value(e, terms, out, depth + 1);
+        out.push_str(",\n");
+    }
+    indent(out, depth);
+    out.push(')');
+}
+
+/// The value of a string argument without its quotes, any other argument as is
+pub fn unquote(s: &str) -> Cow<'_, str> {
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(raw) => Literal {
+            raw,
+            quote: b'"',
+            escapes: Escapes::Common,
+        }
+        .unescape(),
+        None => Cow::Borrowed(s),
+    }
+}
+
+/// Quote `s` as a lighttpd string
+pub fn quote_literal(s: &str) -> String {
+    let mut q = String::with_capacity(s.len() + 2);
+    q.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        // a backslash not starting an escape is kept, like in `"^www\."`
+        let next = chars.peek().copied();
+        if c == '\\' && next.is_some_and(|n| Escapes::Common.unescape(n, Some('"')).is_none()) {
+            q.push(c);
+        } else {
+            Escapes::Common.escape(c, &mut q);
+        }
+    }
+    q.push('"');
+    q
+}
+
+fn unexpected<'a>(input: &'a [u8], expected: &[&str]) -> nom::Err<Error<&'a [u8]>> {
+    let (start, _) = trivia(input).unwrap_or((input, input));
+    let kind = if start.is_empty() {
+        ErrorKind::UnexpectedEof
+    } else {
+        ErrorKind::UnexpectedToken
+    };
+    Error::new(start, kind).expected(expected).cut()
+}
+
+/// Error at the end of input, `input` is any suffix of it
+fn unclosed_block(input: &[u8]) -> nom::Err<Error<&[u8]>> {
+    Error::new(&input[input.len()..], ErrorKind::UnbalancedBrace)
+        .expected(&["}"])
+        .cut()
+}
+
+/// Parse statements from `input`, which is a suffix of `base`, stops before
+/// the `}` closing the block
+fn parse_block<'a>(
+    base: &'a [u8],
+    mut input: &'a [u8],
+) -> IResult<&'a [u8], Vec<Directive<Lighttpd>>> {
+    let mut result = vec![];
+    loop {
+        let (start, _) = trivia(input)?;
+        let (rest, tok) = tokenizer(start)?;
+        let word = match tok {
+            Token::Word(w) => w,
+            Token::BlockEnd | Token::Eof => break,
+            _ => return Err(unexpected(start, &["statement"])),
+        };
+        let mut d = Directive::<Lighttpd>::default();
+        d.span.name = Position::new(base.len() - start.len());
+        let rest = if word.starts_with('$') {
+            let (rest, name) = condition_name(word, rest)?;
+            d.name = name;
+            let rest = condition(base, rest, &mut d)?;
+            parse_children(base, rest, &mut d)?
+        } else if word == "else" || word == "elseif" {
+            d.name = "else".into();
+            let mut rest = rest;
+            if word == "else" {
+                if let (r, Token::Word("if")) = tokenizer(rest)? {
+                    rest = r;
+                }
+            }
+            let (arg, _) = trivia(rest)?;
+            if let (r, Token::Word(w)) = tokenizer(arg)? {
+                let (r, name) = condition_name(w, r)?;
+                push_arg(base, &mut d, arg, name);
+                rest = condition(base, r, &mut d)?;
+            }
+            parse_children(base, rest, &mut d)?
+        } else {
+            d.name = word.into();
+            match tokenizer(rest)? {
+                (r, Token::Operator(op @ ("=" | "+=" | ":="))) => {
+                    let (arg, _) = trivia(rest)?;
+                    push_arg(base, &mut d, arg, op.into());
+                    parse_value(base, r, &mut d)?
+                }
+                _ if word == "include" || word == "include_shell" => {
+                    parse_value(base, rest, &mut d)?
+                }
+                _ => return Err(unexpected(rest, &["=", "+=", ":="])),
+            }
+        };
+        d.span.range = base.len() - start.len()..base.len() - rest.len();
+        result.push(d);
+        input = rest;
+    }
+    Ok((input, result))
+}
+
+fn push_arg(base: &[u8], d: &mut Directive<Lighttpd>, at: &[u8], value: String) {
+    d.args.push(value);
+    d.span.args.push(Position::new(base.len() - at.len()));
+}
+
+/// `$HTTP["host"]` after `$HTTP`
+fn condition_name<'a>(word: &str, input: &'a [u8]) -> IResult<&'a [u8], String> {
+    let (rest, tok) = tokenizer(input)?;
+    if tok != Token::LeftBracket {
+        return Ok((input, word.into()));
+    }
+    let (rest, field) = tokenizer(rest)?;
+    let Token::Literal(field) = field else {
+        return Err(unexpected(rest, &["string"]));
+    };
+    let (end, tok) = tokenizer(rest)?;
+    if tok != Token::RightBracket {
+        return Err(unexpected(rest, &["]"]));
+    }
+    Ok((end, format!("{word}[\"{}\"]", field.raw)))
+}
+
+/// Operator and pattern of a condition
+fn condition<'a>(
+    base: &'a [u8],
+    input: &'a [u8],
+    d: &mut Directive<Lighttpd>,
+) -> Result<&'a [u8], nom::Err<Error<&'a [u8]>>> {
+    let (arg, _) = trivia(input)?;
+    let (rest, tok) = tokenizer(arg)?;
+    let Token::Operator(op @ ("==" | "!=" | "=~" | "!~" | "=^" | "=$")) = tok else {
+        return Err(unexpected(arg, &["==", "!=", "=~", "!~"]));
+    };
+    push_arg(base, d, arg, op.into());
+    let (arg, _) = trivia(rest)?;
+    let (rest, tok) = tokenizer(arg)?;
+    let Token::Literal(pattern) = tok else {
+        return Err(unexpected(arg, &["string"]));
+    };
+    push_arg(base, d, arg, pattern.into());
+    Ok(rest)
+}
+
+/// `{ ... }` of a conditional
+fn parse_children<'a>(
+    base: &'a [u8],
+    input: &'a [u8],
+    d: &mut Directive<Lighttpd>,
+) -> Result<&'a [u8], nom::Err<Error<&'a [u8]>>> {
+    let (rest, tok) = tokenizer(input)?;
+    if tok != Token::BlockStart {
+        return Err(unexpected(input, &["{"]));
+    }
+    let (rest, children) = parse_block(base, rest)?;
+    let (rest, tok) = tokenizer(rest)?;
+    if tok != Token::BlockEnd {
+        return Err(unclosed_block(base));
+    }
+    d.children = Some(children);
+    Ok(rest)
+}
+
+/// Value expression, the terms of a concatenation are the arguments, an array
+/// is the children
+fn parse_value<'a>(
+    base: &'a [u8],
+    input: &'a [u8],
+    d: &mut Directive<Lighttpd>,
+) -> Result<&'a [u8], nom::Err<Error<&'a [u8]>>> {
+    if let (rest, Token::LeftParen) = tokenizer(input)? {
+        let (rest, elements) = parse_array(base, rest)?;
+        d.children = Some(elements);
+        return Ok(rest);
+    }
+    let mut rest = parse_term(base, input, d)?;
+    loop {
+        match tokenizer(rest)? {
+            (r, Token::Plus) => rest = parse_term(base, r, d)?,
+            _ => return Ok(rest),
+        }
+    }
+}
+
+fn parse_term<'a>(
+    base: &'a [u8],
+    input: &'a [u8],
+    d: &mut Directive<Lighttpd>,
+) -> Result<&'a [u8], nom::Err<Error<&'a [u8]>>> {
+    let (arg, _) = trivia(input)?;
+    let (rest, tok) = tokenizer(arg)?;
+    let value = match tok {
+        // a string as written, with its quotes
+        Token::Literal(_) => utf8(arg, arg.len() - rest.len())?,
+        Token::Word(w) => w,
+        _ => return Err(unexpected(arg, &["value"])),
+    };
+    push_arg(base, d, arg, value.into());
+    Ok(rest)
+}
+
+/// Elements of an array after `(`, till the closing `)`
+fn parse_array<'a>(
+    base: &'a [u8],
+    mut input: &'a [u8],
+) -> IResult<&'a [u8], Vec<Directive<Lighttpd>>> {
+    let mut result = vec![];
+    loop {
+        let (start, _) = trivia(input)?;
+        match tokenizer(start)? {
+            (r, Token::RightParen) => return Ok((r, result)),
+            (r, Token::Comma) => {
+                input = r;
+                continue;
+            }
+            _ => {}
+        }
+        let mut e = Directive::<Lighttpd>::default();
+        e.span.name = Position::new(base.len() - start.len());
+        let mut rest = parse_value(base, start, &mut e)?;
+        let (arg, _) = trivia(rest)?;
+        if let (r, Token::Arrow) = tokenizer(arg)? {
+            // what was read is the key, a string
+            if e.args.len() != 1 || e.children.is_some() || !e.args[0].starts_with('"') {
+                return Err(unexpected(arg, &[",", ")"]));
+            }
+            e.name = unquote(&e.args.remove(0)).into_owned();
+            e.span.args.clear();
+            push_arg(base, &mut e, arg, "=>".into());
+            rest = parse_value(base, r, &mut e)?;
+        }
+        e.span.range = base.len() - start.len()..base.len() - rest.len();
+        match tokenizer(rest)? {
+            (_, Token::RightParen | Token::Comma) => {}
+            _ => return Err(unexpected(rest, &[",", ")"])),
+        }
+        result.push(e);
+        input = rest;
+    }
+}
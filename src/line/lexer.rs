@@ -0,0 +1,206 @@
+use nom::{
+    bytes::complete::{tag, take_till, take_while, take_while1},
+    character::is_newline,
+    combinator::{map, opt, value},
+    sequence::tuple,
+};
+
+pub use crate::error::IResult;
+use crate::{
+    error::{Error, ErrorKind},
+    lexer::{self, utf8, Literal},
+};
+
+use super::{Continuation, LineSyntax};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Token<'a> {
+    NewLine,
+    Eof,
+    Literal(Literal<'a>),
+}
+
+impl<'a> Token<'a> {
+    pub fn literal(&self) -> Option<Literal<'a>> {
+        match self {
+            Self::Literal(l) => Some(*l),
+            _ => None,
+        }
+    }
+}
+
+fn newline(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    value(Token::NewLine, tuple((opt(tag(b"\r")), tag(b"\n"))))(input)
+}
+
+fn is_space(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// Line break joining the next line, by the rule of `syntax`
+fn continuation<'a>(syntax: &LineSyntax, input: &'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
+    let rest = match syntax.continuation {
+        Continuation::Backslash => tuple((tag(b"\\"), opt(tag(b"\r")), tag(b"\n")))(input)?.0,
+        Continuation::Indent => {
+            let mut rest = input;
+            loop {
+                let (r, _) = tuple((opt(tag(b"\r")), tag(b"\n"), take_while1(is_space)))(rest)?;
+                rest = r;
+                // an indented comment is skipped, the next line may continue
+                match comment(syntax, rest) {
+                    Ok((r, _)) => rest = r,
+                    Err(_) => break,
+                }
+            }
+            // an indented blank line doesn't continue anything
+            if rest.is_empty() || matches!(rest[0], b'\r' | b'\n') {
+                return Err(nom::Err::Error(Error::new(
+                    input,
+                    ErrorKind::UnexpectedToken,
+                )));
+            }
+            rest
+        }
+        Continuation::None => {
+            return Err(nom::Err::Error(Error::new(
+                input,
+                ErrorKind::UnexpectedToken,
+            )));
+        }
+    };
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+/// Comment till the end of line, excluding the newline
+pub fn comment<'a>(syntax: &LineSyntax, input: &'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
+    map(tuple((tag([syntax.comment]), take_till(is_newline))), |x| {
+        x.1
+    })(input)
+}
+
+/// Word till whitespace or line continuation, read by the escapes of `syntax`
+/// like a quoted one, an escaped char doesn't end it
+fn word<'a>(syntax: &LineSyntax, input: &'a [u8], stop: &[u8]) -> IResult<&'a [u8], Literal<'a>> {
+    let mut len = 0;
+    while len < input.len() && !input[len].is_ascii_whitespace() && !stop.contains(&input[len]) {
+        if continuation(syntax, &input[len..]).is_ok() {
+            break;
+        }
+        len += match input.get(len..len + 2) {
+            Some([b'\\', c])
+                if !is_newline(*c)
+                    && *c != b'\r'
+                    && syntax.escapes.unescape(char::from(*c), None).is_some() =>
+            {
+                2
+            }
+            _ => 1,
+        };
+    }
+    if len == 0 {
+        return Err(nom::Err::Error(Error::new(
+            input,
+            ErrorKind::UnexpectedToken,
+        )));
+    }
+    let lit = Literal {
+        raw: utf8(input, len)?,
+        escapes: syntax.escapes,
+        ..Default::default()
+    };
+    Ok((&input[len..], lit))
+}
+
+/// Quoted literal, a quote the escapes don't escape ends at the next one
+fn quoted<'a>(syntax: &LineSyntax, input: &'a [u8]) -> IResult<&'a [u8], Literal<'a>> {
+    let quote = input[0];
+    let (rest, raw) = match syntax
+        .escapes
+        .unescape(char::from(quote), Some(char::from(quote)))
+    {
+        Some(_) => {
+            let (rest, lit) = lexer::quoted(input)?;
+            (rest, lit.raw)
+        }
+        None => {
+            let len = input[1..]
+                .iter()
+                .position(|&b| b == quote)
+                .ok_or_else(|| Error::new(input, ErrorKind::UnterminatedQuote).cut())?;
+            (&input[len + 2..], utf8(&input[1..], len)?)
+        }
+    };
+    let lit = Literal {
+        raw,
+        quote,
+        escapes: syntax.escapes,
+    };
+    Ok((rest, lit))
+}
+
+/// Chars ending an unquoted word besides whitespaces
+fn stops(syntax: &LineSyntax, keyword: bool) -> Vec<u8> {
+    let mut stop = vec![];
+    if syntax.inline_comment {
+        stop.push(syntax.comment);
+    }
+    if let Some(sep) = syntax.separator.filter(|_| keyword) {
+        stop.push(sep);
+    }
+    stop
+}
+
+/// Keyword of a line, quoted like an argument or not, followed by the optional
+/// separator
+pub fn keyword<'a>(syntax: &LineSyntax, input: &'a [u8]) -> IResult<&'a [u8], Literal<'a>> {
+    let (mut rest, lit) = match input.first() {
+        Some(q) if syntax.quotes.contains(q) => quoted(syntax, input),
+        _ => word(syntax, input, &stops(syntax, true)),
+    }?;
+    if let Some(sep) = syntax.separator {
+        let (r, _) = trivia(syntax, rest)?;
+        if r.first() == Some(&sep) {
+            rest = &r[1..];
+        }
+    }
+    Ok((rest, lit))
+}
+
+fn literal<'a>(syntax: &LineSyntax, input: &'a [u8]) -> IResult<&'a [u8], Token<'a>> {
+    let (rest, lit) = match input.first() {
+        Some(q) if syntax.quotes.contains(q) => quoted(syntax, input),
+        _ => word(syntax, input, &stops(syntax, false)),
+    }?;
+    Ok((rest, Token::Literal(lit)))
+}
+
+/// Skip whitespaces and line continuations, returns the skipped text
+pub fn trivia<'a>(syntax: &LineSyntax, input: &'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
+    let mut rest = input;
+    loop {
+        let (r, _) = take_while(is_space)(rest)?;
+        rest = r;
+        match continuation(syntax, rest) {
+            Ok((r, _)) => rest = r,
+            Err(_) => break,
+        }
+    }
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+pub fn tokenizer<'a>(syntax: &LineSyntax, input: &'a [u8]) -> IResult<&'a [u8], Token<'a>> {
+    let (mut input, _) = trivia(syntax, input)?;
+    if syntax.inline_comment {
+        if let Ok((rest, _)) = comment(syntax, input) {
+            input = rest;
+        }
+    }
+    if input.is_empty() {
+        return Ok((input, Token::Eof));
+    }
+
+    match newline(input) {
+        Ok(res) => Ok(res),
+        Err(_) => literal(syntax, input),
+    }
+}
@@ -0,0 +1,374 @@
+//! Generic parser for line oriented `keyword args...` configuration
+//!
+//! Formats like `sshd_config`, postfix `main.cf` or `redis.conf` differ only in
+//! details, which are described by a [`LineSyntax`]. A scheme implementing
+//! [`LineScheme`] gets the parser and renderer for free:
+//!
+//! ```
+//! use misc_conf::{ast::*, line::*};
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Postfix;
+//!
+//! impl LineScheme for Postfix {
+//!     const SYNTAX: LineSyntax = LineSyntax::new()
+//!         .separator(b'=')
+//!         .continuation(Continuation::Indent);
+//! }
+//!
+//! let conf = Directive::<Postfix>::parse(b"mydestination = localhost,\n    example.com\n").unwrap();
+//! assert_eq!(conf[0].args, ["localhost,", "example.com"]);
+//! ```
+//!
+//! A section keyword starts a section lasting until the next one, a block
+//! keyword starts a block lasting until its close keyword, the lines between
+//! are their children.
+
+pub mod lexer;
+
+use std::borrow::Cow;
+
+use crate::{
+    ast::{Directive, DirectiveTrait, IncludeContext, Value},
+    error::{Error, ErrorKind, ParseError},
+    lexer::{Escapes, LineIndex, Literal, Position},
+    render::{indent, quote_with, Render},
+    utils::*,
+};
+
+use self::lexer::*;
+
+/// How a line is continued on the next one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Continuation {
+    None,
+    /// `\` at the end of line
+    Backslash,
+    /// The next line starts with whitespaces
+    Indent,
+}
+
+/// Escape rules of a format differing from the common `\\` and `\"`
+#[derive(Debug, Clone, Copy)]
+pub struct Escape {
+    /// Value of a name or argument
+    pub unescape: fn(Literal<'_>) -> String,
+    /// Quote a value if necessary, so that it's read back as one argument
+    pub quote: fn(&str) -> Cow<'_, str>,
+}
+
+/// Syntax of a line oriented format, built with the const methods
+#[derive(Debug, Clone, Copy)]
+pub struct LineSyntax {
+    /// Char starting a comment
+    pub comment: u8,
+    /// Whether a comment may follow the arguments, otherwise only whole lines
+    pub inline_comment: bool,
+    pub continuation: Continuation,
+    /// Optional char between the keyword and the arguments, like `=`
+    pub separator: Option<u8>,
+    /// Chars quoting an argument, among `"` and `'`
+    pub quotes: &'static [u8],
+    /// Keywords starting a section which lasts until the next section
+    pub sections: &'static [&'static str],
+    /// Keywords starting a block and the keywords closing them
+    pub blocks: &'static [(&'static str, &'static str)],
+    /// Keywords including other files, every argument is a glob
+    pub include: &'static [&'static str],
+    /// Whether the keywords above are case-insensitive
+    pub ignore_case: bool,
+    /// Escape rules of the names and arguments, quoted or not
+    pub escapes: Escapes,
+    /// Custom escape rules of the values, `escapes` still tells where a word or
+    /// a quoted string ends
+    pub escape: Option<Escape>,
+}
+
+impl Default for LineSyntax {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineSyntax {
+    /// `#` comments at the start of a line, `"` quotes, no escapes, no
+    /// continuation, sections, blocks nor include
+    pub const fn new() -> Self {
+        Self {
+            comment: b'#',
+            inline_comment: false,
+            continuation: Continuation::None,
+            separator: None,
+            quotes: b"\"",
+            sections: &[],
+            blocks: &[],
+            include: &[],
+            ignore_case: false,
+            escapes: Escapes::Raw,
+            escape: None,
+        }
+    }
+
+    pub const fn comment(mut self, comment: u8, inline: bool) -> Self {
+        self.comment = comment;
+        self.inline_comment = inline;
+        self
+    }
+
+    pub const fn continuation(mut self, continuation: Continuation) -> Self {
+        self.continuation = continuation;
+        self
+    }
+
+    pub const fn separator(mut self, separator: u8) -> Self {
+        self.separator = Some(separator);
+        self
+    }
+
+    pub const fn quotes(mut self, quotes: &'static [u8]) -> Self {
+        self.quotes = quotes;
+        self
+    }
+
+    pub const fn sections(mut self, sections: &'static [&'static str]) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    pub const fn blocks(mut self, blocks: &'static [(&'static str, &'static str)]) -> Self {
+        self.blocks = blocks;
+        self
+    }
+
+    pub const fn include(mut self, include: &'static [&'static str]) -> Self {
+        self.include = include;
+        self
+    }
+
+    pub const fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+
+    pub const fn escapes(mut self, escapes: Escapes) -> Self {
+        self.escapes = escapes;
+        self
+    }
+
+    pub const fn escape(mut self, escape: Escape) -> Self {
+        self.escape = Some(escape);
+        self
+    }
+
+    fn keyword_eq(&self, a: &str, b: &str) -> bool {
+        if self.ignore_case {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    }
+
+    pub fn is_section(&self, name: &str) -> bool {
+        self.sections.iter().any(|s| self.keyword_eq(s, name))
+    }
+
+    pub fn is_include(&self, name: &str) -> bool {
+        self.include.iter().any(|s| self.keyword_eq(s, name))
+    }
+
+    /// The close keyword if `name` starts a block
+    pub fn block_close(&self, name: &str) -> Option<&'static str> {
+        self.blocks
+            .iter()
+            .find(|(open, _)| self.keyword_eq(open, name))
+            .map(|(_, close)| *close)
+    }
+
+    fn is_block_close(&self, name: &str) -> bool {
+        self.blocks
+            .iter()
+            .any(|(_, close)| self.keyword_eq(close, name))
+    }
+
+    pub(crate) fn unescape(&self, lit: Literal<'_>) -> String {
+        match self.escape {
+            Some(escape) => (escape.unescape)(lit),
+            None => lit.into(),
+        }
+    }
+
+    /// Quote a name or argument if necessary
+    pub fn quote<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        if let Some(escape) = self.escape {
+            return (escape.quote)(s);
+        }
+        let mut leading = String::from(self.comment as char);
+        leading.extend(self.separator.map(char::from));
+        let special = if self.inline_comment {
+            String::from(self.comment as char)
+        } else {
+            String::new()
+        };
+        let quoted = quote_with(s, &special, &leading, self.escapes);
+        // without escapes, a `"` can only be in single quotes
+        let single = self.escapes == Escapes::Raw
+            && self.quotes.contains(&b'\'')
+            && s.contains('"')
+            && !s.contains('\'');
+        if single {
+            return Cow::Owned(format!("'{s}'"));
+        }
+        quoted
+    }
+}
+
+/// Scheme of a line oriented format
+pub trait LineScheme: Clone + Default {
+    const SYNTAX: LineSyntax;
+}
+
+impl<S: LineScheme> DirectiveTrait<S> for Directive<S> {
+    fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>> {
+        let (_, mut result) =
+            parse_lines(&S::SYNTAX, input).map_err(|err| ParseError::from_nom(input, err))?;
+        let index = LineIndex::new(input);
+        for d in result.iter_mut() {
+            d.locate(&index);
+        }
+        Ok(result)
+    }
+
+    fn resolve_include_inner(
+        mut self,
+        ctx: &mut IncludeContext,
+        out: &mut Vec<Self>,
+    ) -> anyhow::Result<()> {
+        if S::SYNTAX.is_include(&self.name) {
+            // every argument is a glob, and no match is not an error
+            let mut paths = vec![];
+            for pattern in self.args.iter() {
+                paths.extend(glob_include(ctx.fs, pattern, &ctx.root, ctx.res)?);
+            }
+            self.resolve_files(paths, ctx, out)?;
+        } else {
+            self.resolve_include(ctx)?;
+            out.push(self);
+        }
+        Ok(())
+    }
+}
+
+impl<S: LineScheme, T: Value + AsRef<str>> Render for Directive<S, T> {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        let syntax = &S::SYNTAX;
+        indent(out, depth);
+        out.push_str(&syntax.quote(self.name.as_ref()));
+        for arg in &self.args {
+            out.push(' ');
+            out.push_str(&syntax.quote(arg.as_ref()));
+        }
+        out.push('\n');
+        if let Some(children) = self.body() {
+            children.render_to(out, depth + 1);
+            if let Some(close) = syntax.block_close(self.name.as_ref()) {
+                indent(out, depth);
+                out.push_str(close);
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Parse all lines of `base`, the open section and blocks are kept in a stack
+fn parse_lines<'a, S: Clone + Default>(
+    syntax: &LineSyntax,
+    base: &'a [u8],
+) -> IResult<&'a [u8], Vec<Directive<S>>> {
+    let offset = |i: &[u8]| base.len() - i.len();
+    let mut result = vec![];
+    let mut stack: Vec<Directive<S>> = vec![];
+    let mut input = base;
+    loop {
+        let (start, _) = trivia(syntax, input)?;
+        if let Ok((rest, _)) = comment(syntax, start) {
+            input = rest;
+            continue;
+        }
+        match tokenizer(syntax, start)? {
+            (rest, Token::NewLine) => {
+                input = rest;
+                continue;
+            }
+            (_, Token::Eof) => break,
+            _ => {}
+        }
+        let (mut rest, name) = keyword(syntax, start)?;
+        let mut d = Directive::<S> {
+            name: syntax.unescape(name),
+            ..Default::default()
+        };
+        d.span.name = Position::new(offset(start));
+
+        let end = loop {
+            let (arg, _) = trivia(syntax, rest)?;
+            let (r, tok) = tokenizer(syntax, arg)?;
+            match tok {
+                Token::Literal(l) => {
+                    d.args.push(syntax.unescape(l));
+                    d.span.args.push(Position::new(offset(arg)));
+                    rest = r;
+                }
+                _ => break rest,
+            }
+        };
+        d.span.range = offset(start)..offset(end);
+        input = end;
+
+        let open_block = stack.last().and_then(|top| syntax.block_close(&top.name));
+        if syntax.is_section(&d.name) {
+            if let Some(close) = open_block {
+                let err = Error::new(start, ErrorKind::UnexpectedToken)
+                    .expected(&[close])
+                    .found(d.name);
+                return Err(err.cut());
+            }
+            result.extend(stack.pop());
+            d.children = Some(vec![]);
+            stack.push(d);
+        } else if syntax.block_close(&d.name).is_some() {
+            d.children = Some(vec![]);
+            stack.push(d);
+        } else if open_block.is_some_and(|close| syntax.keyword_eq(close, &d.name)) {
+            let mut top = stack.pop().unwrap();
+            top.span.range.end = d.span.range.end;
+            push_child(&mut stack, &mut result, top);
+        } else if syntax.is_block_close(&d.name) {
+            let err = Error::new(start, ErrorKind::UnexpectedToken)
+                .expected(&open_block.map_or(vec!["directive"], |c| vec![c]))
+                .found(d.name);
+            return Err(err.cut());
+        } else {
+            push_child(&mut stack, &mut result, d);
+        }
+    }
+    if let Some(close) = stack.last().and_then(|top| syntax.block_close(&top.name)) {
+        let err = Error::new(input, ErrorKind::UnexpectedEof).expected(&[close]);
+        return Err(err.cut());
+    }
+    result.extend(stack.pop());
+    Ok((input, result))
+}
+
+fn push_child<S: Clone + Default>(
+    stack: &mut [Directive<S>],
+    result: &mut Vec<Directive<S>>,
+    d: Directive<S>,
+) {
+    match stack.last_mut() {
+        Some(top) => {
+            top.span.range.end = d.span.range.end;
+            top.children.get_or_insert_with(Vec::new).push(d);
+        }
+        None => result.push(d),
+    }
+}
@@ -0,0 +1,102 @@
+//! Lossless parser for nginx configuration
+
+use std::borrow::Cow;
+
+use super::{lexer::*, lua_block_len, quote_literal, unclosed_block, Nginx};
+use crate::{
+    cst::{self, text, Block, CstScheme, Node},
+    error::{Error, ErrorKind, ParseError},
+    lexer::Escapes,
+};
+
+impl CstScheme for Nginx {
+    fn parse_cst(input: &[u8]) -> anyhow::Result<Block<Self>> {
+        let (rest, block) = parse_block(input).map_err(|err| ParseError::from_nom(input, err))?;
+        if !rest.is_empty() {
+            let offset = input.len() - rest.len();
+            return Err(ParseError::new(input, offset, ErrorKind::UnbalancedBrace).into());
+        }
+        Ok(block)
+    }
+
+    fn quote(s: &str) -> Cow<'_, str> {
+        quote_literal(s)
+    }
+
+    const ESCAPES: Escapes = Escapes::Nginx;
+}
+
+fn unexpected<'a>(input: &'a [u8], expected: &[&str]) -> nom::Err<Error<&'a [u8]>> {
+    let (start, _) = trivia(input).unwrap_or((input, input));
+    let kind = if start.is_empty() {
+        ErrorKind::UnexpectedEof
+    } else {
+        ErrorKind::UnexpectedToken
+    };
+    Error::new(start, kind).expected(expected).cut()
+}
+
+/// Token at `input` with the trivia before it
+fn next_token(input: &[u8]) -> IResult<&[u8], (Token<'_>, cst::Token)> {
+    let (start, _) = trivia(input)?;
+    let (rest, tok) = tokenizer(start)?;
+    let leading = text(input, start)?;
+    let raw = text(start, rest)?;
+    Ok((rest, (tok, cst::Token { leading, raw })))
+}
+
+fn parse_block(mut input: &[u8]) -> IResult<&[u8], Block<Nginx>> {
+    let mut block = Block::default();
+    loop {
+        let (rest, (tok, name)) = next_token(input)?;
+        let lit = match tok {
+            Token::Literal(lit) => lit,
+            Token::BlockEnd | Token::Eof => {
+                // leave the closing brace to the caller
+                let (start, _) = trivia(input)?;
+                block.trailing = text(input, start)?;
+                return Ok((start, block));
+            }
+            _ => return Err(unexpected(input, &["directive"])),
+        };
+
+        let mut node = Node {
+            name,
+            args: vec![],
+            head_end: Default::default(),
+            body: None,
+            tail: Default::default(),
+        };
+        input = rest;
+        loop {
+            let (rest, (tok, token)) = next_token(input)?;
+            let head = std::mem::replace(&mut input, rest);
+            match tok {
+                Token::Literal(_) => {
+                    node.args.push(token);
+                    continue;
+                }
+                Token::Semicolon => {}
+                Token::BlockStart if lit.raw.ends_with("_by_lua_block") => {
+                    let len = lua_block_len(rest).ok_or_else(|| unclosed_block(rest))?;
+                    node.tail.raw = text(rest, &rest[len..])?;
+                    input = &rest[len..];
+                }
+                Token::BlockStart => {
+                    let (rest, body) = parse_block(rest)?;
+                    let (rest, (tok, tail)) = next_token(rest)?;
+                    if tok != Token::BlockEnd {
+                        return Err(unclosed_block(rest));
+                    }
+                    node.body = Some(body);
+                    node.tail = tail;
+                    input = rest;
+                }
+                _ => return Err(unexpected(head, &[";", "{"])),
+            }
+            node.head_end = token;
+            break;
+        }
+        block.nodes.push(node);
+    }
+}
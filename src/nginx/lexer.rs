@@ -1,4 +1,3 @@
-use nom::character::streaming::anychar;
 #[allow(unused_imports)]
 use nom::{
     branch::alt,
@@ -9,14 +8,11 @@ use nom::{
         streaming::space1,
     },
     combinator::{fail, map, map_res, opt, value},
-    error::VerboseError,
-    number::complete::be_u8,
     sequence::{delimited, tuple},
 };
 
-use crate::lexer::Literal;
-
-pub type IResult<I, O> = nom::IResult<I, O, VerboseError<I>>;
+pub use crate::error::IResult;
+use crate::lexer::{quoted, unquoted, Escapes, Literal};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Token<'a> {
@@ -46,19 +42,19 @@ impl<'a> Token<'a> {
     }
 }
 
-fn starttag(input: &[u8]) -> IResult<&[u8], Token> {
+fn starttag(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     value(Token::BlockStart, tag(b"{"))(input)
 }
 
-fn endtag(input: &[u8]) -> IResult<&[u8], Token> {
+fn endtag(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     value(Token::BlockEnd, tag(b"}"))(input)
 }
 
-// fn newline(input: &[u8]) -> IResult<&[u8], Token> {
+// fn newline(input: &[u8]) -> IResult<&[u8], Token<'_>> {
 //     value(Token::NewLine, tuple((opt(tag(b"\r")), tag(b"\n"))))(input)
 // }
 
-fn semicolon(input: &[u8]) -> IResult<&[u8], Token> {
+fn semicolon(input: &[u8]) -> IResult<&[u8], Token<'_>> {
     value(Token::Semicolon, tag(b";"))(input)
 }
 
@@ -74,59 +70,38 @@ fn comment(input: &[u8]) -> IResult<&[u8], &[u8]> {
     )(input)
 }
 
-fn literal(input: &[u8]) -> IResult<&[u8], Token> {
-    let (_, mut first) = be_u8(input)?;
-
-    let (input, raw) = match first {
-        b'"' => map_res(
-            // for empty string
-            map(
-                delimited(
-                    cchar(first as _),
-                    opt(escaped(none_of(r#"\""#), '\\', anychar)),
-                    cchar(first as _),
-                ),
-                Option::unwrap_or_default,
-            ),
-            std::str::from_utf8,
-        )(input),
-        b'\'' => map_res(
-            // for empty string
-            map(
-                delimited(
-                    cchar(first as _),
-                    opt(escaped(none_of(r#"\'"#), '\\', anychar)),
-                    cchar(first as _),
-                ),
-                Option::unwrap_or_default,
-            ),
-            std::str::from_utf8,
-        )(input),
-        _ => {
-            first = 0;
-            map_res(
-                escaped(none_of(" \t\r\n;'\"\\"), '\\', anychar),
-                std::str::from_utf8,
-            )(input)
-        }
+fn literal(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (rest, lit) = match input.first() {
+        Some(b'"' | b'\'') => quoted(input),
+        _ => unquoted(input, " \t\r\n;'\"\\"),
     }?;
-    Ok((input, Token::Literal(Literal { raw, quote: first })))
+    let lit = Literal {
+        escapes: Escapes::Nginx,
+        ..lit
+    };
+    Ok((rest, Token::Literal(lit)))
+}
+
+pub fn tokenizer(input: &[u8]) -> IResult<&[u8], Token<'_>> {
+    let (input, _) = trivia(input)?;
+    if input.is_empty() {
+        return Ok((input, Token::Eof));
+    }
+
+    alt((starttag, endtag, semicolon, literal))(input)
 }
 
-pub fn tokenizer(mut input: &[u8]) -> IResult<&[u8], Token> {
+/// Skip whitespaces and comments, returns the skipped text
+pub fn trivia(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let mut rest = input;
     loop {
-        let (rest, cmt) = space_and_comment(input)?;
-        input = rest;
+        let (r, cmt) = space_and_comment(rest)?;
+        rest = r;
         if cmt.is_none() {
             break;
         }
     }
-
-    if input.len() == 0 {
-        return Ok((input, Token::Eof));
-    }
-
-    alt((starttag, endtag, semicolon, literal))(input)
+    Ok((rest, &input[..input.len() - rest.len()]))
 }
 
 fn space_and_comment(input: &[u8]) -> IResult<&[u8], Option<&[u8]>> {
@@ -1,153 +1,350 @@
 //! Nom parser for nginx configuration
+//!
+//! Like nginx, `\"`, `\'`, `\\`, `\t`, `\r` and `\n` are unescaped in quoted and unquoted
+//! tokens, any other backslash is kept, so a regex like `~ \.php$` is read as written.
+//!
+//! Relative include paths are in the directory of the main configuration file,
+//! in nested included files too, see [`include_context`].
 
+mod cst;
 pub mod lexer;
 
 use std::path::Path;
 
 use crate::{
-    ast::{Directive, DirectiveTrait},
-    lexer::{line_column2, Literal},
+    ast::{Directive, DirectiveTrait, IncludeContext, RawBlock, Value},
+    error::{recover, Diagnostics, Error, ErrorKind, ParseError},
+    lexer::{utf8, Escapes, LineIndex, Literal, Position},
+    render::{indent, quote_with, Render},
     utils::*,
 };
 
 use self::lexer::*;
 
 use anyhow::Context;
-use nom::{
-    combinator::{fail, map},
-    error::{ContextError, ParseError, VerboseError},
-    multi::many0,
-};
 
 #[derive(Debug, Clone, Default)]
 pub struct Nginx;
 
+/// Include context of `nginx -p prefix -c conf`: relative includes are in the
+/// directory of `conf`, itself relative to `prefix`
+pub fn include_context<'a>(prefix: &Path, conf: &Path) -> IncludeContext<'a> {
+    let conf = prefix.join(conf);
+    IncludeContext::new(conf.parent().unwrap_or(prefix))
+}
+
+/// Parse configuration text into directives whose names and arguments are `T`,
+/// a [`Literal`] borrows them from `input` without any copy
+pub fn parse<'a, T>(input: &'a [u8]) -> anyhow::Result<Vec<Directive<Nginx, T>>>
+where
+    T: Value + From<Literal<'a>>,
+{
+    let (rest, mut result) =
+        parse_block(input, input, &mut None).map_err(|err| ParseError::from_nom(input, err))?;
+    let (rest, _) = trivia(rest).map_err(|err| ParseError::from_nom(input, err))?;
+    if !rest.is_empty() {
+        let offset = input.len() - rest.len();
+        return Err(ParseError::new(input, offset, ErrorKind::UnbalancedBrace).into());
+    }
+    let index = LineIndex::new(input);
+    for d in result.iter_mut() {
+        d.locate(&index);
+    }
+    Ok(result)
+}
+
 impl DirectiveTrait<Nginx> for Directive<Nginx> {
     fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>> {
-        let res = parse_block(input).map_err(|err| {
-            err.map(|e| {
-                let errs = e
-                    .errors
-                    .iter()
-                    .map(|(i, code)| {
-                        let ((l, c), pos) = line_column2(input, i).unwrap();
-                        format!("0x{pos:x}({l}:{c}) err: {:?}", code)
-                    })
-                    .collect::<Vec<_>>();
-                anyhow::anyhow!("{}", errs.join("\n"))
-            })
-        })?;
-        Ok(res.1)
+        parse(input)
+    }
+
+    fn parse_recovering(input: &[u8]) -> (Vec<Self>, Vec<ParseError>) {
+        let mut diag = Some(vec![]);
+        let mut result = vec![];
+        let mut rest = input;
+        loop {
+            match parse_block(input, rest, &mut diag) {
+                Ok((r, res)) => {
+                    result.extend(res);
+                    rest = r;
+                }
+                Err(err) => {
+                    recover(&mut diag, err).ok();
+                    break;
+                }
+            }
+            // a `}` without block to close
+            match trivia(rest) {
+                Ok((r, _)) if !r.is_empty() => {
+                    let err = Error::new(r, ErrorKind::UnbalancedBrace);
+                    recover(&mut diag, err.cut()).ok();
+                    rest = &r[1..];
+                }
+                _ => break,
+            }
+        }
+
+        let index = LineIndex::new(input);
+        for d in result.iter_mut() {
+            d.locate(&index);
+        }
+        let errors = diag.unwrap_or_default();
+        let errors = errors.into_iter().map(|e| ParseError::from_error(input, e));
+        (result, errors.collect())
     }
 
     fn resolve_include_inner(
         mut self,
-        dir: &Path,
+        ctx: &mut IncludeContext,
         out: &mut Vec<Self>,
-        res: Option<ResolvePath>,
     ) -> anyhow::Result<()> {
         if self.name == "include" {
-            let path = Path::new(
-                self.args
-                    .get(0)
-                    .context("include directive expect one arg")?
-                    .as_str(),
-            );
-            for path in glob::glob(
-                &res.resolve(&if path.is_absolute() {
-                    path.to_path_buf()
-                } else {
-                    dir.join(path)
-                })?
-                .to_string_lossy(),
-            )?
-            .flatten()
-            {
-                let data = std::fs::read(res.resolve(&path)?)?;
-                for c in Self::parse(&data).with_context(|| format!("parse {path:?}"))? {
-                    c.resolve_include_inner(dir, out, res)?;
-                }
-            }
+            let pattern = self
+                .args
+                .first()
+                .context("include directive expect one arg")?;
+            let paths = glob_include(ctx.fs, pattern, &ctx.root, ctx.res)?;
+            self.resolve_files(paths, ctx, out)?;
         } else {
-            self.resolve_include(dir, res)?;
+            self.resolve_include(ctx)?;
             out.push(self);
         }
         Ok(())
     }
 }
 
-fn parse_literal(input: &[u8]) -> IResult<&[u8], Literal<'_>> {
-    let (rest, tok) = tokenizer(input)?;
-    match tok {
-        Token::Literal(l) => Ok((rest, l)),
-        Token::Eof | Token::BlockEnd => Ok((rest, Default::default())),
-        _else => fail(input),
+impl<T: Value + AsRef<str>> Render for Directive<Nginx, T> {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        indent(out, depth);
+        out.push_str(&quote_literal(self.name.as_ref()));
+        for arg in &self.args {
+            out.push(' ');
+            out.push_str(&quote_literal(arg.as_ref()));
+        }
+        if let Some(raw) = self.raw_block.as_ref() {
+            out.push_str(" {");
+            out.push_str(&raw.source);
+            out.push_str("}\n");
+        } else if let Some(children) = self.body() {
+            out.push_str(" {\n");
+            children.render_to(out, depth + 1);
+            indent(out, depth);
+            out.push_str("}\n");
+        } else {
+            out.push_str(";\n");
+        }
     }
 }
 
-fn parse_block(mut input: &[u8]) -> IResult<&[u8], Vec<Directive<Nginx>>> {
-    let mut result = vec![];
-    loop {
-        let mut d = Directive::default();
-        let (rest, tag) = tokenizer(input).map_err(|err| {
-            err.map(|err| VerboseError::add_context(input, "unexpected item token", err))
-        })?;
-
-        let lit = match tag {
-            Token::Literal(lit) => lit,
-            Token::BlockEnd | Token::Eof => break,
-            _ => return fail(input),
-        };
-        d.name = lit.clone().into();
-        let (rest, args) = map(many0(parse_literal), |v| {
-            v.into_iter().map(Into::into).collect()
-        })(rest)?;
-        d.args = args;
+/// Quote a name or argument for nginx configuration if necessary
+pub fn quote_literal(s: &str) -> std::borrow::Cow<'_, str> {
+    quote_with(s, ";", "{}#", Escapes::Nginx)
+}
 
-        let (rest, tok) = tokenizer(rest)?;
-        match tok {
-            Token::Semicolon | Token::NewLine => {
-                input = rest;
+/// Length of the lua code after `*_by_lua_block {`, including the closing brace,
+/// `None` if the block is not closed
+///
+/// Like lua-nginx-module, braces in strings and comments are skipped, the bytes
+/// are scanned once without any UTF-8 conversion.
+fn lua_block_len(input: &[u8]) -> Option<usize> {
+    let mut pairs = 1usize;
+    let mut i = 0;
+    while let Some(&b) = input.get(i) {
+        i += match b {
+            b'{' => {
+                pairs += 1;
+                1
             }
-            Token::Eof => break,
-            Token::BlockStart if lit.raw.ends_with("_by_lua_block") => {
-                use luaparse::token::*;
-
-                let mut pairs = 1usize;
-                let cow = String::from_utf8_lossy(rest);
-                let mut lexer = luaparse::Lexer::new(luaparse::InputCursor::new(&cow));
-                while let Some(Ok(tok)) = lexer.next() {
-                    match tok.value {
-                        TokenValue::Symbol(Symbol::CurlyBracketLeft) => pairs += 1,
-                        TokenValue::Symbol(Symbol::CurlyBracketRight) => pairs -= 1,
-                        _ => {}
-                    }
-                    if pairs == 0 {
-                        break;
-                    }
+            b'}' => {
+                pairs -= 1;
+                if pairs == 0 {
+                    return Some(i + 1);
                 }
-
-                input = &rest[lexer.cursor().pos().byte..];
+                1
             }
-            Token::BlockStart => {
-                let (rest, res) = parse_block(rest)?;
-                d.children.replace(res);
-                let (rest, tok) = tokenizer(rest)?;
-                if tok != Token::BlockEnd {
-                    return Err(nom::Err::Failure(VerboseError::add_context(
-                        input,
-                        "expected block end brace",
-                        VerboseError::from_error_kind(input, nom::error::ErrorKind::Fail),
-                    )));
+            b'"' | b'\'' => lua_string_len(&input[i..])?,
+            b'[' => lua_long_bracket_len(&input[i..]).unwrap_or(Some(1))?,
+            b'-' if input[i..].starts_with(b"--") => {
+                let comment = &input[i + 2..];
+                match lua_long_bracket_len(comment) {
+                    Some(len) => 2 + len?,
+                    None => 2 + comment.iter().take_while(|&&b| b != b'\n').count(),
                 }
+            }
+            _ => 1,
+        };
+    }
+    None
+}
+
+/// Length of the short string starting `input` with its quotes, `None` if not
+/// closed
+fn lua_string_len(input: &[u8]) -> Option<usize> {
+    let quote = input[0];
+    let mut i = 1;
+    while let Some(&b) = input.get(i) {
+        match b {
+            b'\\' => i += 2,
+            _ if b == quote => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Length of the long string or comment body `[==[ ... ]==]` starting `input`,
+/// `None` if `input` doesn't start with a long bracket, `Some(None)` if not
+/// closed
+fn lua_long_bracket_len(input: &[u8]) -> Option<Option<usize>> {
+    let rest = input.strip_prefix(b"[")?;
+    let level = rest.iter().take_while(|&&b| b == b'=').count();
+    if rest.get(level) != Some(&b'[') {
+        return None;
+    }
+    let open = level + 2;
+    let mut close = vec![b'='; level + 2];
+    close[0] = b']';
+    close[level + 1] = b']';
+    Some(
+        input[open..]
+            .windows(close.len())
+            .position(|w| w == close.as_slice())
+            .map(|pos| open + pos + close.len()),
+    )
+}
+
+/// Parse directives from `input`, which is a suffix of `base`, stops before
+/// the `}` closing the block
+///
+/// When recovering, a directive with error is skipped to the next `;` or `}`.
+fn parse_block<'a, T: Value + From<Literal<'a>>>(
+    base: &'a [u8],
+    mut input: &'a [u8],
+    diag: &mut Diagnostics<'a>,
+) -> IResult<&'a [u8], Vec<Directive<Nginx, T>>> {
+    let mut result = vec![];
+    loop {
+        match parse_directive(base, input, diag) {
+            Ok((_, None)) => break,
+            Ok((rest, Some(d))) => {
+                result.push(d);
                 input = rest;
             }
-            _ => {
-                fail::<_, (), _>(rest)?;
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) if diag.is_some() => {
+                input = skip_directive(e.input);
+                recover(diag, nom::Err::Failure(e))?;
             }
+            Err(err) => return Err(err),
         }
-
-        result.push(d);
     }
     Ok((input, result))
 }
+
+/// Parse the next directive, `None` at the end of the block
+fn parse_directive<'a, T: Value + From<Literal<'a>>>(
+    base: &'a [u8],
+    input: &'a [u8],
+    diag: &mut Diagnostics<'a>,
+) -> IResult<&'a [u8], Option<Directive<Nginx, T>>> {
+    let offset = |i: &[u8]| base.len() - i.len();
+    let mut d = Directive::default();
+    let (start, _) = trivia(input)?;
+    let (mut rest, tag) = tokenizer(start)?;
+    let lit = match tag {
+        Token::Literal(lit) => lit,
+        Token::BlockEnd | Token::Eof => return Ok((input, None)),
+        _ => {
+            let err = Error::new(start, ErrorKind::UnexpectedToken).expected(&["directive"]);
+            return Err(err.cut());
+        }
+    };
+    d.name = lit.into();
+    d.span.name = Position::new(offset(start));
+
+    let (end, tok) = loop {
+        let (arg, _) = trivia(rest)?;
+        let (r, tok) = tokenizer(arg)?;
+        match tok {
+            Token::Literal(l) => {
+                d.args.push(l.into());
+                d.span.args.push(Position::new(offset(arg)));
+                rest = r;
+            }
+            _ => break (arg, tok),
+        }
+    };
+    let (rest, _) = tokenizer(end)?;
+    let input = match tok {
+        Token::Semicolon | Token::NewLine => rest,
+        Token::BlockStart if lit.raw.ends_with("_by_lua_block") => {
+            let (len, end) = match lua_block_len(rest) {
+                Some(len) => (len - 1, len),
+                None => {
+                    recover(diag, unclosed_block(base))?;
+                    (rest.len(), rest.len())
+                }
+            };
+            d.raw_block = Some(RawBlock {
+                source: utf8(rest, len)?.into(),
+                range: offset(rest)..offset(rest) + len,
+                start: Position::new(offset(rest)),
+            });
+            &rest[end..]
+        }
+        Token::BlockStart => {
+            let (rest, res) = parse_block(base, rest, diag)?;
+            d.children.replace(res);
+            let (rest, tok) = tokenizer(rest)?;
+            if tok != Token::BlockEnd {
+                recover(diag, unclosed_block(base))?;
+            }
+            rest
+        }
+        Token::Eof => {
+            let err = Error::new(end, ErrorKind::UnexpectedEof).expected(&[";", "{"]);
+            return Err(err.cut());
+        }
+        _ => {
+            let err = Error::new(end, ErrorKind::UnexpectedToken).expected(&[";", "{"]);
+            return Err(err.cut());
+        }
+    };
+
+    d.span.range = offset(start)..offset(input);
+    Ok((input, Some(d)))
+}
+
+/// Skip the rest of a directive from an error at `input`, stops after `;`, after a
+/// whole block, or before the `}` closing the enclosing block
+fn skip_directive(mut input: &[u8]) -> &[u8] {
+    let mut depth = 0usize;
+    loop {
+        let start = trivia(input).map_or(input, |x| x.0);
+        let Ok((rest, tok)) = tokenizer(start) else {
+            // like unterminated quote, try again from the next char
+            input = &start[1..];
+            continue;
+        };
+        match tok {
+            Token::Eof => return start,
+            Token::Semicolon if depth == 0 => return rest,
+            Token::BlockStart => depth += 1,
+            Token::BlockEnd if depth == 0 => return start,
+            Token::BlockEnd => {
+                depth -= 1;
+                if depth == 0 {
+                    return rest;
+                }
+            }
+            _ => {}
+        }
+        input = rest;
+    }
+}
+
+/// Error at the end of input, `input` is any suffix of it
+fn unclosed_block(input: &[u8]) -> nom::Err<Error<&[u8]>> {
+    Error::new(&input[input.len()..], ErrorKind::UnbalancedBrace)
+        .expected(&["}"])
+        .cut()
+}
@@ -0,0 +1,105 @@
+//! Redis `redis.conf`, on top of the generic [`line`](crate::line) parser
+//!
+//! Quoted arguments follow the rules of Redis: escapes like `\n` and `\xff` in
+//! double quotes, only `\'` in single quotes.
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use crate::{
+    ast::Directive,
+    lexer::{Escapes, Literal},
+    line::{Escape, LineScheme, LineSyntax},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Redis;
+
+impl LineScheme for Redis {
+    const SYNTAX: LineSyntax = LineSyntax::new()
+        .quotes(b"\"'")
+        .include(&["include"])
+        .ignore_case()
+        .escapes(Escapes::Common)
+        .escape(Escape { unescape, quote });
+}
+
+/// Value of a literal by the rules of Redis, a `\xHH` escape is taken as the
+/// char U+00HH
+pub fn unescape(lit: Literal<'_>) -> String {
+    match lit.quote {
+        b'"' => {}
+        b'\'' => return lit.raw.replace("\\'", "'"),
+        _ => return lit.raw.into(),
+    }
+    let mut s = String::with_capacity(lit.raw.len());
+    let mut chars = lit.raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => s.push('\n'),
+            Some('r') => s.push('\r'),
+            Some('t') => s.push('\t'),
+            Some('b') => s.push('\u{8}'),
+            Some('a') => s.push('\u{7}'),
+            Some('x') => {
+                let hex = chars.as_str().get(..2);
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        s.push(char::from(b));
+                        chars.nth(1);
+                    }
+                    None => s.push('x'),
+                }
+            }
+            Some(c) => s.push(c),
+            None => s.push('\\'),
+        }
+    }
+    s
+}
+
+/// Double-quote `s` with Redis escapes if it would not be read back as one
+/// argument otherwise
+pub fn quote(s: &str) -> Cow<'_, str> {
+    let plain = !s.is_empty()
+        && !s.starts_with(['#', '"', '\''])
+        && !s.contains(|c: char| c.is_whitespace() || c.is_ascii_control());
+    if plain {
+        return Cow::Borrowed(s);
+    }
+
+    let mut q = String::with_capacity(s.len() + 2);
+    q.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' | '"' => {
+                q.push('\\');
+                q.push(c);
+            }
+            '\n' => q.push_str("\\n"),
+            '\r' => q.push_str("\\r"),
+            '\t' => q.push_str("\\t"),
+            c if c.is_ascii_control() => q.push_str(&format!("\\x{:02x}", c as u8)),
+            _ => q.push(c),
+        }
+    }
+    q.push('"');
+    Cow::Owned(q)
+}
+
+/// Commands renamed by `rename-command`, keyed by the lowercase original name,
+/// an empty new name means the command is disabled
+///
+/// Includes should be resolved before, the last rename of a command wins.
+pub fn renamed_commands(dirs: &[Directive<Redis>]) -> BTreeMap<String, String> {
+    dirs.iter()
+        .filter(|d| d.name.eq_ignore_ascii_case("rename-command"))
+        .filter_map(|d| match d.args.as_slice() {
+            [from, to] => Some((from.to_ascii_lowercase(), to.clone())),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,62 @@
+//! Render AST back to configuration text
+
+use std::borrow::Cow;
+
+use crate::lexer::Escapes;
+
+/// Render a parsed tree back to text which parses to the same tree
+pub trait Render {
+    /// Append the rendered text to `out`, indented for the nesting `depth`
+    fn render_to(&self, out: &mut String, depth: usize);
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        self.render_to(&mut out, 0);
+        out
+    }
+}
+
+impl<R: Render> Render for [R] {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        for d in self {
+            d.render_to(out, depth);
+        }
+    }
+}
+
+pub(crate) fn indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("    ");
+    }
+}
+
+/// Double-quote `s` if it would not be read back as one literal otherwise,
+/// i.e. it's empty, contains whitespace, quotes or one of `special`, or starts
+/// with one of `leading`
+pub(crate) fn quote<'a>(s: &'a str, special: &str, leading: &str) -> Cow<'a, str> {
+    quote_with(s, special, leading, Escapes::Common)
+}
+
+/// Like [`quote`], by the `escapes` rules of the scheme
+pub(crate) fn quote_with<'a>(
+    s: &'a str,
+    special: &str,
+    leading: &str,
+    escapes: Escapes,
+) -> Cow<'a, str> {
+    let plain = !s.is_empty()
+        && !s.starts_with(|c| leading.contains(c))
+        && escapes.is_plain(s)
+        && !s.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || special.contains(c));
+    if plain {
+        return Cow::Borrowed(s);
+    }
+
+    let mut q = String::with_capacity(s.len() + 2);
+    q.push('"');
+    for c in s.chars() {
+        escapes.escape(c, &mut q);
+    }
+    q.push('"');
+    Cow::Owned(q)
+}
@@ -0,0 +1,197 @@
+//! Nom parser for squid configuration
+//!
+//! The preprocessor conditional `if ... else ... endif` is a directive named `if`
+//! with the condition as arguments and the lines inside as children, the `else`
+//! branch is its last child named `else`.
+//!
+//! Lines are read by the [`line`](crate::line) lexer. Like squid without
+//! `configuration_includes_quoted_values`, a quoted argument keeps its quotes,
+//! e.g. the format of `logformat x "%rm %ru"` is `"%rm %ru"`. So names and
+//! arguments are rendered as stored, without quoting.
+
+use std::borrow::Cow;
+
+use anyhow::Context;
+
+use crate::{
+    ast::{Directive, DirectiveTrait, IncludeContext, Value},
+    error::{Error, ErrorKind, ParseError},
+    lexer::{Escapes, LineIndex, Literal, Position},
+    line::{
+        lexer::{comment, tokenizer, trivia, IResult, Token},
+        Continuation, Escape, LineSyntax,
+    },
+    render::{indent, Render},
+    utils::*,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Squid;
+
+const SYNTAX: LineSyntax = LineSyntax::new()
+    .continuation(Continuation::Backslash)
+    // for `\"` in quoted strings, the values are kept as is
+    .escapes(Escapes::Common)
+    .escape(Escape {
+        unescape,
+        quote: quote_literal,
+    });
+
+impl DirectiveTrait<Squid> for Directive<Squid> {
+    fn parse(input: &[u8]) -> anyhow::Result<Vec<Self>> {
+        let (_, mut result) = parse_lines(input).map_err(|err| ParseError::from_nom(input, err))?;
+        let index = LineIndex::new(input);
+        for d in result.iter_mut() {
+            d.locate(&index);
+        }
+        Ok(result)
+    }
+
+    fn resolve_include_inner(
+        mut self,
+        ctx: &mut IncludeContext,
+        out: &mut Vec<Self>,
+    ) -> anyhow::Result<()> {
+        if self.name == "include" {
+            let pattern = self
+                .args
+                .first()
+                .context("include directive expect one arg")?;
+            let paths = glob_include(ctx.fs, pattern, &ctx.root, ctx.res)?;
+            self.resolve_files(paths, ctx, out)?;
+        } else {
+            self.resolve_include(ctx)?;
+            out.push(self);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Value + AsRef<str>> Render for Directive<Squid, T> {
+    fn render_to(&self, out: &mut String, depth: usize) {
+        indent(out, depth);
+        out.push_str(&quote_literal(self.name.as_ref()));
+        for arg in &self.args {
+            out.push(' ');
+            out.push_str(&quote_literal(arg.as_ref()));
+        }
+        out.push('\n');
+        if let Some(children) = self.body() {
+            for c in children {
+                // the else branch is at the same level as its if
+                let branch = c.name.as_ref() == "else" && c.children.is_some();
+                c.render_to(out, if branch { depth } else { depth + 1 });
+            }
+            if self.name.as_ref() == "if" {
+                indent(out, depth);
+                out.push_str("endif\n");
+            }
+        }
+    }
+}
+
+/// A name or argument for squid configuration, written as stored since a quoted
+/// one keeps its quotes
+pub fn quote_literal(s: &str) -> Cow<'_, str> {
+    Cow::Borrowed(s)
+}
+
+/// Raw text of a literal, with the quotes if any
+fn unescape(lit: Literal<'_>) -> String {
+    match lit.quote {
+        0 => lit.raw.into(),
+        q => format!("{q}{}{q}", lit.raw, q = char::from(q)),
+    }
+}
+
+/// Parse all lines of `base`, conditionals are nested with a stack
+fn parse_lines(base: &[u8]) -> IResult<&[u8], Vec<Directive<Squid>>> {
+    let offset = |i: &[u8]| base.len() - i.len();
+    let mut result = vec![];
+    // open `if` and `else`
+    let mut stack: Vec<Directive<Squid>> = vec![];
+    let mut input = base;
+    loop {
+        let (start, _) = trivia(&SYNTAX, input)?;
+        if let Ok((rest, _)) = comment(&SYNTAX, start) {
+            input = rest;
+            continue;
+        }
+        let (mut rest, tok) = tokenizer(&SYNTAX, start)?;
+        let lit = match tok {
+            Token::Literal(lit) => lit,
+            Token::NewLine => {
+                input = rest;
+                continue;
+            }
+            Token::Eof => break,
+        };
+        let mut d = Directive::<Squid> {
+            name: SYNTAX.unescape(lit),
+            ..Default::default()
+        };
+        d.span.name = Position::new(offset(start));
+
+        let end = loop {
+            let (arg, _) = trivia(&SYNTAX, rest)?;
+            let (r, tok) = tokenizer(&SYNTAX, arg)?;
+            match tok {
+                Token::Literal(l) => {
+                    d.args.push(SYNTAX.unescape(l));
+                    d.span.args.push(Position::new(offset(arg)));
+                    rest = r;
+                }
+                _ => break rest,
+            }
+        };
+        d.span.range = offset(start)..offset(end);
+        input = end;
+
+        match d.name.as_str() {
+            "if" => {
+                d.children = Some(vec![]);
+                stack.push(d);
+            }
+            "else" if stack.last().is_some_and(|d| d.name == "if") => {
+                d.children = Some(vec![]);
+                stack.push(d);
+            }
+            "endif" if !stack.is_empty() => {
+                let mut top = stack.pop().unwrap();
+                if top.name == "else" {
+                    let mut parent = stack.pop().unwrap();
+                    parent.children.get_or_insert_with(Vec::new).push(top);
+                    top = parent;
+                }
+                top.span.range.end = d.span.range.end;
+                push_child(&mut stack, &mut result, top);
+            }
+            "else" | "endif" => {
+                let err = Error::new(start, ErrorKind::UnexpectedToken)
+                    .expected(&["directive"])
+                    .found(d.name);
+                return Err(err.cut());
+            }
+            _ => push_child(&mut stack, &mut result, d),
+        }
+    }
+    if !stack.is_empty() {
+        let err = Error::new(input, ErrorKind::UnexpectedEof).expected(&["endif"]);
+        return Err(err.cut());
+    }
+    Ok((input, result))
+}
+
+fn push_child(
+    stack: &mut [Directive<Squid>],
+    result: &mut Vec<Directive<Squid>>,
+    d: Directive<Squid>,
+) {
+    match stack.last_mut() {
+        Some(top) => {
+            top.span.range.end = d.span.range.end;
+            top.children.get_or_insert_with(Vec::new).push(d);
+        }
+        None => result.push(d),
+    }
+}
@@ -0,0 +1,25 @@
+//! OpenSSH `sshd_config` and `ssh_config`, on top of the generic [`line`](crate::line) parser
+//!
+//! A `Match` or `Host` line starts a section which lasts until the next one, the
+//! lines between are its children. Keywords are case-insensitive, as in OpenSSH.
+//! `\\` and `\"` are escapes, other backslashes are kept.
+
+use crate::{
+    lexer::Escapes,
+    line::{LineScheme, LineSyntax},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Ssh;
+
+/// Keywords starting a section
+pub const SECTIONS: &[&str] = &["Match", "Host"];
+
+impl LineScheme for Ssh {
+    const SYNTAX: LineSyntax = LineSyntax::new()
+        .separator(b'=')
+        .sections(SECTIONS)
+        .include(&["Include"])
+        .ignore_case()
+        .escapes(Escapes::Common);
+}
@@ -3,6 +3,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use crate::fs::ConfigFs;
+
 pub type ResolvePath<'a> = &'a dyn Fn(&Path) -> anyhow::Result<PathBuf>;
 
 pub trait PathResolver {
@@ -19,6 +21,27 @@ impl PathResolver for Option<ResolvePath<'_>> {
     }
 }
 
+/// Files of `fs` matched by the glob `pattern` of an include directive, a
+/// relative pattern is relative to `dir`
+pub fn glob_include(
+    fs: &dyn ConfigFs,
+    pattern: &str,
+    dir: &Path,
+    res: Option<ResolvePath>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let path = Path::new(pattern);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        dir.join(path)
+    };
+    let mut result = vec![];
+    for path in fs.glob(&res.resolve(&path)?.to_string_lossy())? {
+        result.push(res.resolve(&path)?.into_owned());
+    }
+    Ok(result)
+}
+
 pub fn replace_slice<T>(source: &[T], from: &[T], to: &[T]) -> Vec<T>
 where
     T: Clone + PartialEq,
@@ -1,7 +1,7 @@
 use std::path::Path;
 
 use misc_conf::apache::Apache;
-use misc_conf::ast::Config;
+use misc_conf::ast::{Config, Directive, DirectiveTrait};
 
 fn parse(path: impl AsRef<Path>) -> Config<Apache> {
     let path = path.as_ref();
@@ -26,7 +26,52 @@ fn verify_result() {
     let files = cfg.root.query("files");
     assert_eq!(files.len(), 1);
     assert_eq!(files[0].args[0], "\\.ht*");
-    assert_eq!(cfg.root.query("MultiLineString")[0].args[0], "abc\ndef");
+    // joined like httpd, with no whitespace in between
+    assert_eq!(cfg.root.query("MultiLineString")[0].args, ["abcdef"]);
+}
+
+#[test]
+fn continuation() {
+    let cfg = parse("tests/apache/confcase/continuation.conf");
+    let root = &cfg.root;
+    assert_eq!(
+        root.query("CustomLog")[0].args,
+        [
+            "logs/ssl_request.log",
+            r#"%t %h %{SSL_PROTOCOL}x %{SSL_CIPHER}x "%r" %b"#
+        ]
+    );
+    assert_eq!(root.query("Header")[0].args, ["set", "X-Joined", "one two"]);
+    // trailing whitespace after the backslash
+    assert_eq!(
+        root.query("ServerAlias")[0].args,
+        ["example.com", "www.example.com"]
+    );
+    assert_eq!(root.query("Split")[0].args, ["abcdef"]);
+    assert_eq!(root.query("Directory")[0].args, ["/var/www"]);
+    assert_eq!(
+        root.query("Directory/Options")[0].args,
+        ["Indexes", "FollowSymLinks"]
+    );
+    assert_eq!(root.query("CRLF")[0].args, ["one", "two"]);
+
+    let cfg = parse("tests/apache/confcase/crlf.conf");
+    let names = cfg
+        .root_directives()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["Name", "key", "b[[}", "Header", "ServerAlias", "Directory"]
+    );
+    let root = &cfg.root;
+    assert_eq!(root.query("Header")[0].args, ["set", "X-Joined", "one two"]);
+    assert_eq!(root.query("Directory")[0].args, ["/var/www"]);
+    assert_eq!(
+        root.query("Directory/Options")[0].args,
+        ["IndexesFollowSymLinks"]
+    );
 }
 
 #[test]
@@ -38,6 +83,47 @@ fn include() {
     assert_eq!(cfg.root_directives()[0].name, "DefaultRuntimeDir");
 }
 
+#[test]
+fn include_server_root() {
+    use misc_conf::ast::{Config, IncludeContext};
+    use misc_conf::fs::MemFs;
+
+    let fs: MemFs = [
+        (
+            "/etc/httpd/httpd.conf",
+            "ServerRoot \"/srv/httpd\"\nInclude conf/extra/*.conf\n\
+             <IfModule ssl_module>\n    Include conf/ssl.conf\n</IfModule>\n",
+        ),
+        (
+            "/srv/httpd/conf/extra/mpm.conf",
+            "StartServers 2\nInclude conf/mods/*.conf\n",
+        ),
+        ("/srv/httpd/conf/mods/status.conf", "ExtendedStatus On\n"),
+        ("/srv/httpd/conf/ssl.conf", "SSLEngine on\n"),
+        // next to the configuration file, not in ServerRoot
+        ("/etc/httpd/conf/ssl.conf", "SSLEngine off\n"),
+    ]
+    .into_iter()
+    .collect();
+    let mut cfg = Config::<Apache>::parse_in(&fs, "/etc/httpd/httpd.conf".into()).unwrap();
+    let mut ctx = IncludeContext::new("/etc/httpd");
+    ctx.fs = &fs;
+    cfg.resolve_include_with(&mut ctx).unwrap();
+
+    let names = cfg
+        .root_directives()
+        .iter()
+        .map(|d| d.name.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["ServerRoot", "StartServers", "ExtendedStatus", "IfModule"]
+    );
+    let ssl = cfg.root.query_ref("IfModule/SSLEngine")[0];
+    assert_eq!(ssl.args, ["on"]);
+    assert_eq!(ssl.span.to_string(), "/srv/httpd/conf/ssl.conf:1:1");
+}
+
 #[test]
 fn string() {
     let cfg = parse("tests/apache/confcase/string.conf");
@@ -45,6 +131,23 @@ fn string() {
 
     let res = cfg.root.query("Files");
     assert!(res[0].args[0] == "\\.ht*");
+
+    let formats = cfg.root.query("LogFormat");
+    // not escaped, so the quotes inside end the argument
+    assert_eq!(formats[2].args, ["%h %l %u %t ", "%r", " %>s %O", "common"]);
+    let res = cfg.root.query("IfModule/LogFormat");
+    assert_eq!(
+        res[0].args[0],
+        r#"%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-Agent}i""#
+    );
+    assert_eq!(res[1].args, [r#"%h %l %u %t "%r" %>s %b"#, "common"]);
+
+    let data = br#"Alias "/a\\b" '/it\'s' "/keep\'"
+RewriteRule ^/(.*)\.html$ /$1 [L]
+"#;
+    let res = Directive::<Apache>::parse(data).unwrap();
+    assert_eq!(res[0].args, [r"/a\b", "/it's", r"/keep\'"]);
+    assert_eq!(res[1].args[0], r"^/(.*)\.html$");
 }
 
 #[test]
@@ -59,3 +162,93 @@ fn cpath() {
         .cpath_query(&CPathBuf::parse("//ServerAdmin").unwrap());
     println!("{res:#?}");
 }
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::ast::*;
+    use misc_conf::render::Render;
+
+    for path in glob::glob("tests/apache/**/*.conf").unwrap().flatten() {
+        let cfg = parse(&path);
+        let text = cfg.render();
+        let reparsed = Directive::<Apache>::parse(text.as_bytes())
+            .unwrap_or_else(|e| panic!("reparse {path:?}: {e}\n{text}"));
+        assert_eq!(reparsed, cfg.root_directives(), "{path:?}\n{text}");
+        assert_eq!(reparsed.render(), text);
+    }
+
+    // names which need quotes or escapes
+    for name in [
+        "it's", "<x", "a>b", r"\", r"a\", r"a\b", "#x", "a b", r#"a"b"#, r#"\""#,
+    ] {
+        let mut section = Directive::<Apache>::block(name, ["/"]);
+        section.push_child(Directive::new(name, [name]));
+        let text = section.render();
+        let reparsed = Directive::<Apache>::parse(text.as_bytes())
+            .unwrap_or_else(|e| panic!("reparse {name:?}: {e}\n{text}"));
+        assert_eq!(reparsed, [section], "{text}");
+    }
+}
+
+#[test]
+fn span() {
+    let mut cfg = parse("tests/apache/confcase/include.conf");
+    cfg.resolve_include(None, None).unwrap();
+
+    let dir = cfg.root.query("Directory").remove(1);
+    assert_eq!(dir.args, ["/usr/share"]);
+    assert_eq!(
+        dir.span.to_string(),
+        "tests/apache/confcase/httpd.conf:22:2"
+    );
+    assert_eq!(dir.span.args[0].column, 12);
+
+    let require = &dir.children.as_ref().unwrap()[1];
+    assert_eq!(
+        require.span.to_string(),
+        "tests/apache/confcase/httpd.conf:24:9"
+    );
+    let cols = require
+        .span
+        .args
+        .iter()
+        .map(|p| p.column)
+        .collect::<Vec<_>>();
+    assert_eq!(cols, [17, 21]);
+
+    let data = std::fs::read("tests/apache/confcase/httpd.conf").unwrap();
+    let text = std::str::from_utf8(&data[dir.span.range.clone()]).unwrap();
+    assert!(text.starts_with("<Directory /usr/share>"));
+    assert!(text.ends_with("</Directory>"));
+}
+
+#[test]
+fn borrowed() {
+    use misc_conf::ast::*;
+    use misc_conf::lexer::Literal;
+
+    fn owned(d: &Directive<Apache, Literal>) -> Directive<Apache> {
+        let mut o = Directive::new(d.name, d.args.iter().copied());
+        o.children = d.children.as_ref().map(|c| c.iter().map(owned).collect());
+        o
+    }
+
+    for path in glob::glob("tests/apache/**/*.conf").unwrap().flatten() {
+        let data = std::fs::read(&path).unwrap();
+        let borrowed = misc_conf::apache::parse::<Literal>(&data).unwrap();
+        let owned = borrowed.iter().map(owned).collect::<Vec<_>>();
+        assert_eq!(
+            owned,
+            Directive::<Apache>::parse(&data).unwrap(),
+            "{path:?}"
+        );
+    }
+
+    let data = std::fs::read("tests/apache/confcase/string.conf").unwrap();
+    let root = misc_conf::apache::parse::<Literal>(&data).unwrap();
+    let module = root.iter().find(|d| d.name.raw == "IfModule").unwrap();
+    let format = module.query_ref("LogFormat")[0].args[0];
+    // escapes are kept in the borrowed text
+    assert!(format.raw.starts_with(r#"%h %l %u %t \"%r\""#));
+    assert!(format.to_string().starts_with(r#"%h %l %u %t "%r""#));
+}
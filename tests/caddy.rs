@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use misc_conf::ast::*;
+use misc_conf::caddy::Caddy;
+
+fn parse(path: impl AsRef<Path>) -> Config<Caddy> {
+    let path = path.as_ref();
+    println!("parsing: {:?}", path);
+    Config::<Caddy>::parse(path.to_path_buf()).unwrap()
+}
+
+#[test]
+fn sites() {
+    let conf = parse("tests/caddy/Caddyfile");
+    let names = conf
+        .root_directives()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["", "(logging)", "example.com", "(proxy)", "api.example.com"]
+    );
+
+    let site = &conf.root_directives()[2];
+    assert_eq!(site.args, ["www.example.com"]);
+    assert_eq!(site.span.args[0].column, 14);
+
+    let global = conf.root.query("/email").pop().unwrap();
+    assert_eq!(global.args, ["admin@example.com"]);
+
+    let respond = conf.root.query("example.com/respond").pop().unwrap();
+    assert_eq!(respond.args, ["/health", r#"{"status": "ok"}"#, "200"]);
+
+    let handle = conf.root.query("example.com/handle").pop().unwrap();
+    assert_eq!(handle.args, ["@api"]);
+
+    let respond = conf.root.query("api.example.com/respond").pop().unwrap();
+    assert_eq!(
+        respond.args,
+        ["<html>\n  <body>{http.request.host}</body>\n</html>", "200"]
+    );
+    let header = conf.root.query("api.example.com/header").pop().unwrap();
+    assert_eq!(header.args[1], "application/json; charset=utf-8");
+}
+
+#[test]
+fn import() {
+    let mut conf = parse("tests/caddy/Caddyfile");
+    conf.resolve_include(None, None).unwrap();
+
+    let output = conf.root.query("example.com/log/output").pop().unwrap();
+    assert_eq!(output.args, ["file", "/var/log/caddy/example.log"]);
+    let proxy = conf
+        .root
+        .query("example.com/handle/reverse_proxy")
+        .pop()
+        .unwrap();
+    assert_eq!(proxy.args, ["localhost:8080", "localhost:8081"]);
+    assert_eq!(
+        proxy.children.as_ref().unwrap()[0].args,
+        ["X-Real-IP", "{remote_host}"]
+    );
+
+    let site = &conf.root_directives()[4];
+    let names = site
+        .children
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["tls", "header", "header", "respond"]);
+    assert_eq!(
+        site.children.as_ref().unwrap()[0].span.to_string(),
+        "tests/caddy/sites/common.caddy:1:1"
+    );
+}
+
+#[test]
+fn errors() {
+    use misc_conf::error::{ErrorKind, ParseError};
+
+    let err = Directive::<Caddy>::parse(b"example.com {\n\trespond ok\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnbalancedBrace);
+
+    let err = Directive::<Caddy>::parse(b"respond <<EOF\nabc\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnterminatedQuote);
+}
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::render::Render;
+
+    let conf = parse("tests/caddy/Caddyfile");
+    let text = conf.render();
+    let reparsed = Directive::<Caddy>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf.root_directives(), "{text}");
+    assert_eq!(reparsed.render(), text);
+}
+
+fn snippet_error(text: &str) -> misc_conf::error::IncludeError {
+    let fs: misc_conf::fs::MemFs = [("Caddyfile", text)].into_iter().collect();
+    let mut conf = Config::<Caddy>::parse_in(&fs, "Caddyfile".into()).unwrap();
+    let mut ctx = IncludeContext::new(".");
+    ctx.fs = &fs;
+    let err = conf.resolve_include_with(&mut ctx).unwrap_err();
+    err.downcast().unwrap()
+}
+
+#[test]
+fn snippet_cycle() {
+    use misc_conf::error::IncludeError;
+
+    let names = |err| match err {
+        IncludeError::Cycle(chain) => chain
+            .iter()
+            .map(|p: &std::path::PathBuf| p.to_string_lossy().into_owned())
+            .collect::<Vec<_>>(),
+        err => panic!("{err}"),
+    };
+
+    let err = snippet_error("(a) {\n\timport a\n}\nexample.com {\n\timport a\n}\n");
+    assert_eq!(names(err), ["(a)", "(a)"]);
+
+    let err =
+        snippet_error("(a) {\n\timport b\n}\n(b) {\n\timport a\n}\nexample.com {\n\timport a\n}\n");
+    // found expanding the `import b` of the definition of `(a)`
+    assert_eq!(names(err), ["(b)", "(a)", "(b)"]);
+
+    // a snippet imported twice in a row is no cycle
+    let fs: misc_conf::fs::MemFs = [(
+        "Caddyfile",
+        "(a) {\n\tgzip\n}\n(b) {\n\timport a\n\timport a\n}\nexample.com {\n\timport b\n}\n",
+    )]
+    .into_iter()
+    .collect();
+    let mut conf = Config::<Caddy>::parse_in(&fs, "Caddyfile".into()).unwrap();
+    conf.resolve_include_with(&mut IncludeContext::new("."))
+        .unwrap();
+    assert_eq!(conf.root.query("example.com/gzip").len(), 2);
+}
+
+#[test]
+fn snippet_depth() {
+    use misc_conf::error::IncludeError;
+
+    let mut text = String::new();
+    for i in 0..10 {
+        text += &format!("(s{i}) {{\n\timport s{}\n}}\n", i + 1);
+    }
+    text += "(s10) {\n\tgzip\n}\nexample.com {\n\timport s0\n}\n";
+    let fs: misc_conf::fs::MemFs = [("Caddyfile", text.as_str())].into_iter().collect();
+    let mut conf = Config::<Caddy>::parse_in(&fs, "Caddyfile".into()).unwrap();
+    let mut ctx = IncludeContext::new(".");
+    ctx.max_depth = 11;
+    conf.clone().resolve_include_with(&mut ctx).unwrap();
+    ctx.max_depth = 10;
+    let err = conf.resolve_include_with(&mut ctx).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<IncludeError>(),
+        Some(IncludeError::TooDeep(10, chain)) if chain.len() == 11
+    ));
+}
@@ -11,3 +11,108 @@ fn test2() {
     let cp = CPathBuf::parse("//'abc def'").unwrap();
     println!("{cp:?}");
 }
+
+fn http() -> misc_conf::ast::Directive<misc_conf::nginx::Nginx> {
+    use misc_conf::ast::*;
+    use misc_conf::nginx::Nginx;
+
+    let data = br#"
+    http {
+        server {
+            listen 80;
+            server_name example.com www.example.com;
+            location / { proxy_pass http://backend; }
+        }
+        server {
+            listen 443 ssl;
+            server_name secure.example.com;
+            ssl_certificate /etc/ssl/secure.pem;
+            location /static { root /var/www; }
+        }
+    }
+    "#;
+    Directive::<Nginx>::parse(data).unwrap().remove(0)
+}
+
+fn query(path: &str) -> Vec<String> {
+    http()
+        .cpath_query(&CPathBuf::parse(path).unwrap())
+        .into_iter()
+        .map(|d| d.query("server_name")[0].args[0].clone())
+        .collect()
+}
+
+#[test]
+fn cond_exists() {
+    assert_eq!(query("//server[ssl_certificate]"), ["secure.example.com"]);
+    assert_eq!(query("//server[^listen$]").len(), 2);
+    assert!(query("//server[ssl_stapling]").is_empty());
+}
+
+#[test]
+fn cond_arg_index() {
+    let root = http();
+    let res = root.cpath_query(&CPathBuf::parse("//listen[1]").unwrap());
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].args, ["443", "ssl"]);
+
+    let res = root.cpath_query(&CPathBuf::parse("//location[0='/static']").unwrap());
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].args, ["/static"]);
+}
+
+#[test]
+fn cond_child_exists() {
+    assert_eq!(query("//server[/location[/proxy_pass]]"), ["example.com"]);
+    assert_eq!(query("//server[/location]").len(), 2);
+}
+
+#[test]
+fn cond_equal() {
+    assert_eq!(query("//server[listen=443]"), ["secure.example.com"]);
+    assert_eq!(
+        query("//server[server_name=www.example.com]"),
+        ["example.com"]
+    );
+    assert!(query("//server[listen=8080]").is_empty());
+}
+
+#[test]
+fn cond_match() {
+    assert_eq!(query("//server[listen~^ssl$]"), ["secure.example.com"]);
+    assert_eq!(query("//server[server_name~example]").len(), 2);
+    assert!(query("//server[server_name~^api]").is_empty());
+}
+
+#[test]
+fn borrowed_matches() {
+    let root = http();
+    let cp = CPathBuf::parse("//location").unwrap();
+    let res = root.cpath_query_ref(&cp);
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[1].args, ["/static"]);
+    assert_eq!(
+        root.cpath_query(&cp),
+        res.into_iter().cloned().collect::<Vec<_>>()
+    );
+
+    let matches = root.cpath_matches(&cp);
+    let server = matches[1].parent().unwrap();
+    assert_eq!(server.name, "server");
+    assert_eq!(
+        server.query_ref("server_name")[0].args,
+        ["secure.example.com"]
+    );
+    let names = matches[0]
+        .ancestors
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["server"]);
+
+    let matches = root.query_matches("server/location/proxy_pass");
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].directive.args, ["http://backend"]);
+    assert_eq!(matches[0].ancestors.len(), 2);
+    assert_eq!(matches[0].parent().unwrap().args, ["/"]);
+}
@@ -0,0 +1,136 @@
+use std::path::Path;
+
+use misc_conf::apache::Apache;
+use misc_conf::ast::*;
+use misc_conf::cst::*;
+use misc_conf::nginx::Nginx;
+
+fn roundtrip<S>(pattern: &str)
+where
+    S: CstScheme + Clone + Default + std::fmt::Debug,
+    Directive<S>: DirectiveTrait<S>,
+{
+    for path in glob::glob(pattern).unwrap().flatten() {
+        println!("parsing: {path:?}");
+        let data = std::fs::read(&path).unwrap();
+        let cst = Cst::<S>::parse(&data).unwrap();
+        assert_eq!(cst.to_string().as_bytes(), data, "{path:?}");
+        assert_eq!(
+            cst.to_directives::<String>(),
+            Directive::<S>::parse(&data).unwrap(),
+            "{path:?}"
+        );
+    }
+}
+
+#[test]
+fn nginx_lossless() {
+    roundtrip::<Nginx>("tests/nginx/**/*.conf");
+}
+
+#[test]
+fn apache_lossless() {
+    roundtrip::<Apache>("tests/apache/**/*.conf");
+}
+
+fn changed_lines(a: &str, b: &str) -> Vec<(String, String)> {
+    assert_eq!(a.lines().count(), b.lines().count());
+    a.lines()
+        .zip(b.lines())
+        .filter(|(x, y)| x != y)
+        .map(|(x, y)| (x.to_string(), y.to_string()))
+        .collect()
+}
+
+#[test]
+fn nginx_edit() {
+    let data = std::fs::read_to_string(Path::new("tests/nginx/few_locations.conf")).unwrap();
+    let mut cst = Cst::<Nginx>::parse(data.as_bytes()).unwrap();
+
+    let http = cst.find_mut("http").next().unwrap();
+    let server = http
+        .body
+        .as_mut()
+        .unwrap()
+        .find_mut("server")
+        .next()
+        .unwrap();
+    let listen = server
+        .body
+        .as_mut()
+        .unwrap()
+        .find_mut("listen")
+        .next()
+        .unwrap();
+    assert_eq!(listen.set_arg(0, "8080").as_deref(), Some("80"));
+    assert_eq!(listen.remove_arg(2).unwrap().raw, "default_server");
+    listen.push_arg("default server");
+    // out of range
+    assert!(listen.set_arg(3, "x").is_none());
+    assert!(listen.remove_arg(3).is_none());
+
+    let text = cst.to_string();
+    let changed = changed_lines(&data, &text);
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].1, r#"        listen 8080 ssl "default server";"#);
+    assert_eq!(
+        Cst::<Nginx>::parse(text.as_bytes())
+            .unwrap()
+            .to_directives::<String>(),
+        Directive::<Nginx>::parse(text.as_bytes()).unwrap()
+    );
+}
+
+#[test]
+fn apache_edit() {
+    let data = std::fs::read_to_string("tests/apache/confcase/string.conf").unwrap();
+    let mut cst = Cst::<Apache>::parse(data.as_bytes()).unwrap();
+
+    let files = cst.find_mut("Files").next().unwrap();
+    assert_eq!(files.args::<String>(), ["\\.ht*"]);
+    let require = files
+        .body
+        .as_mut()
+        .unwrap()
+        .find_mut("require")
+        .next()
+        .unwrap();
+    require.set_arg(1, "granted").unwrap();
+
+    let module = cst.find("IfModule").next().unwrap();
+    let log = module
+        .body
+        .as_ref()
+        .unwrap()
+        .find("LogFormat")
+        .next()
+        .unwrap();
+    assert!(log.name.comments().any(|c| c.contains("format nicknames")));
+
+    let text = cst.to_string();
+    assert_eq!(
+        changed_lines(&data, &text),
+        [(
+            "    Require all denied".to_string(),
+            "    Require all granted".to_string()
+        )]
+    );
+}
+
+#[test]
+fn insert_remove() {
+    let mut cst = Cst::<Nginx>::parse(b"# head\nhttp {\n    gzip on;\n}\n").unwrap();
+    let body = cst.nodes[0].body.as_mut().unwrap();
+    body.insert(1, "\n    # added\n    gzip_types text/plain;")
+        .unwrap();
+    assert_eq!(
+        cst.to_string(),
+        "# head\nhttp {\n    gzip on;\n    # added\n    gzip_types text/plain;\n}\n"
+    );
+
+    cst.nodes[0].body.as_mut().unwrap().remove(0);
+    assert_eq!(
+        cst.to_string(),
+        "# head\nhttp {\n    # added\n    gzip_types text/plain;\n}\n"
+    );
+}
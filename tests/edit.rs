@@ -0,0 +1,163 @@
+use misc_conf::ast::*;
+use misc_conf::cpath::CPathBuf;
+use misc_conf::nginx::Nginx;
+use misc_conf::render::Render;
+
+fn http() -> Directive<Nginx> {
+    let data = br#"
+    http {
+        server {
+            listen 80;
+            server_name example.com;
+            location / { proxy_pass http://backend; }
+        }
+        server {
+            listen 443 ssl;
+            server_tokens on;
+            location /static { root /var/www; }
+        }
+    }
+    "#;
+    let mut root = Directive::block("", Vec::<String>::new());
+    *root.children_mut() = Directive::<Nginx>::parse(data).unwrap();
+    root
+}
+
+#[test]
+fn build() {
+    let mut server = Directive::<Nginx>::block("server", Vec::<String>::new());
+    server.push_child(Directive::new("listen", ["8080"]));
+    server
+        .push_child(Directive::block("location", ["/"]))
+        .push_child(Directive::new("return", ["204"]));
+    server.insert_child(0, Directive::new("server_name", ["a.example.com"]));
+    assert!(Directive::<Nginx>::new("listen", ["80"]).children.is_none());
+
+    let text = server.render();
+    let parsed = Directive::<Nginx>::parse(text.as_bytes()).unwrap();
+    assert_eq!(parsed, [server.clone()], "{text}");
+    let names = server
+        .children
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["server_name", "listen", "location"]);
+}
+
+#[test]
+fn query_mut() {
+    let mut root = http();
+    for listen in root.query_mut("http/server/listen") {
+        listen.push_arg("reuseport");
+    }
+    let listens = root.query("http/server/listen");
+    assert_eq!(listens[0].args, ["80", "reuseport"]);
+    assert_eq!(listens[1].args, ["443", "ssl", "reuseport"]);
+
+    let cp = CPathBuf::parse("//server_tokens[0='on']").unwrap();
+    for d in root.cpath_query_mut(&cp) {
+        d.set_args(["off"]);
+    }
+    assert_eq!(root.query("http/server/server_tokens")[0].args, ["off"]);
+
+    // a match and its descendants are borrowed once
+    let cp = CPathBuf::parse("//server//location").unwrap();
+    assert_eq!(root.cpath_query_mut(&cp).len(), 2);
+    let cp = CPathBuf::parse("//.*").unwrap();
+    assert_eq!(root.cpath_query_mut(&cp).len(), 1);
+}
+
+#[test]
+fn replace_remove() {
+    let mut root = http();
+    let new = Directive::new("proxy_pass", ["http://upstream"]);
+    assert_eq!(root.replace("http/server/location/proxy_pass", new), 1);
+    assert_eq!(
+        root.query("http/server/location/proxy_pass")[0].args,
+        ["http://upstream"]
+    );
+
+    let cp = CPathBuf::parse("//location[0='/static']/root").unwrap();
+    let new = Directive::new("alias", ["/srv/static"]);
+    assert_eq!(root.cpath_replace(&cp, new), 1);
+    assert_eq!(root.query("http/server/location/alias").len(), 1);
+
+    let removed = root.remove("http/server/listen");
+    assert_eq!(removed.len(), 2);
+    assert!(root.query("http/server/listen").is_empty());
+
+    let cp = CPathBuf::parse("//location").unwrap();
+    let removed = root.cpath_remove(&cp);
+    assert_eq!(removed[0].args, ["/"]);
+    assert_eq!(removed[1].args, ["/static"]);
+    assert!(root.query("http/server/location").is_empty());
+    assert_eq!(root.query("http/server").len(), 2);
+}
+
+#[test]
+fn remove_keeps_simple_directives() {
+    let mut root = http();
+    assert!(root.remove("http/server/listen/reuseport").is_empty());
+    let cp = CPathBuf::parse("//listen/.*").unwrap();
+    assert!(root.cpath_remove(&cp).is_empty());
+
+    let listen = &root.query("http/server/listen")[0];
+    assert!(listen.children.is_none());
+    assert_eq!(listen.render(), "listen 80;\n");
+}
+
+#[test]
+fn insert() {
+    let mut root = http();
+    let new = Directive::new("listen", ["[::]:80"]);
+    assert_eq!(root.insert("http/server/listen", Placement::After, new), 2);
+    let listens = root.query("http/server/listen");
+    let args = listens.iter().map(|d| d.args.join(" ")).collect::<Vec<_>>();
+    assert_eq!(args, ["80", "[::]:80", "443 ssl", "[::]:80"]);
+
+    let new = Directive::new("access_log", ["off"]);
+    assert_eq!(root.insert("http/server", Placement::Before, new), 2);
+    let names = root.query_ref("http")[0]
+        .children
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["access_log", "server", "access_log", "server"]);
+    assert_eq!(root.insert("http/upstream", Placement::Before, http()), 0);
+
+    let cp = CPathBuf::parse("//location[0='/static']").unwrap();
+    let mut location = Directive::block("location", ["/api"]);
+    location.push_child(Directive::new("proxy_pass", ["http://api"]));
+    assert_eq!(root.cpath_insert(&cp, Placement::Before, location), 1);
+    let cp = CPathBuf::parse("//location").unwrap();
+    assert_eq!(
+        root.cpath_insert(&cp, Placement::After, Directive::new("gzip", ["on"])),
+        3
+    );
+
+    let server = &root.query_ref("http/server")[1];
+    let names = server
+        .children
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|d| format!("{} {}", d.name, d.args.join(" ")))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "listen 443 ssl",
+            "listen [::]:80",
+            "server_tokens on",
+            "location /api",
+            "gzip on",
+            "location /static",
+            "gzip on",
+        ]
+    );
+    assert_eq!(root.query("http/server/location/proxy_pass").len(), 2);
+}
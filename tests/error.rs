@@ -0,0 +1,198 @@
+use misc_conf::apache::Apache;
+use misc_conf::ast::{Directive, DirectiveTrait};
+use misc_conf::cpath::CPathBuf;
+use misc_conf::error::{ErrorKind, ParseError};
+use misc_conf::nginx::Nginx;
+
+fn nginx(data: &[u8]) -> ParseError {
+    let err = Directive::<Nginx>::parse(data).unwrap_err();
+    println!("{err}");
+    err.downcast_ref::<ParseError>().unwrap().clone()
+}
+
+fn apache(data: &[u8]) -> ParseError {
+    let err = Directive::<Apache>::parse(data).unwrap_err();
+    println!("{err}");
+    err.downcast_ref::<ParseError>().unwrap().clone()
+}
+
+#[test]
+fn unterminated_quote() {
+    let err = nginx(b"http {\n    server_name \"abc;\n}\n");
+    assert_eq!(err.kind, ErrorKind::UnterminatedQuote);
+    assert_eq!((err.position.line, err.position.column), (2, 17));
+    assert_eq!(err.snippet, "    server_name \"abc;\n                ^");
+}
+
+#[test]
+fn unbalanced_brace() {
+    let err = nginx(b"http {\n    server {\n    }\n");
+    assert_eq!(err.kind, ErrorKind::UnbalancedBrace);
+    assert_eq!(err.expected, ["}"]);
+    assert_eq!(err.found, None);
+
+    let err = nginx(b"http {\n}\n}\n");
+    assert_eq!(err.kind, ErrorKind::UnbalancedBrace);
+    assert_eq!((err.position.line, err.position.column), (3, 1));
+    assert_eq!(err.found.as_deref(), Some("}"));
+}
+
+#[test]
+fn unexpected_token() {
+    let err = nginx(b"listen 80 }\n");
+    assert_eq!(err.kind, ErrorKind::UnexpectedToken);
+    assert_eq!(err.expected, [";", "{"]);
+    assert_eq!(err.found.as_deref(), Some("}"));
+
+    let err = nginx(b"listen 80");
+    assert_eq!(err.kind, ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn mismatched_close_tag() {
+    let err = apache(b"<Directory /var/www>\n    Require all granted\n</Files>\n");
+    assert_eq!(err.kind, ErrorKind::MismatchedCloseTag);
+    assert_eq!((err.position.line, err.position.column), (3, 1));
+    assert_eq!(err.expected, ["</Directory>"]);
+    assert_eq!(err.found.as_deref(), Some("</Files>"));
+    assert_eq!(err.snippet, "</Files>\n^");
+}
+
+#[test]
+fn invalid_utf8() {
+    let err = apache(b"ServerName \xff\xfe\n");
+    assert_eq!(err.kind, ErrorKind::InvalidUtf8);
+    assert_eq!((err.position.line, err.position.column), (1, 12));
+
+    let err = nginx(b"server_name \xff;\n");
+    assert_eq!(err.kind, ErrorKind::InvalidUtf8);
+}
+
+#[test]
+fn cpath() {
+    let err = CPathBuf::parse("http/server[listen~'(80']").unwrap_err();
+    println!("{err}");
+    assert_eq!(err.kind, ErrorKind::InvalidRegex);
+
+    let err = CPathBuf::parse("http/server[listen").unwrap_err();
+    println!("{err}");
+    assert_eq!(err.kind, ErrorKind::UnexpectedEof);
+    assert_eq!(err.expected, ["]"]);
+}
+
+#[test]
+fn recover_nginx() {
+    let data = br#"
+user nginx;
+http {
+    server {
+        listen 80 ;;
+        server_name "abc;
+        root /var/www;
+    }
+    { broken; }
+    include mime.types;
+}
+}
+worker_processes 1;
+events {
+    worker_connections 1024;
+"#;
+    let (result, errors) = Directive::<Nginx>::parse_recovering(data);
+    for err in &errors {
+        println!("{err}");
+    }
+    let kinds = errors.iter().map(|e| e.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            ErrorKind::UnexpectedToken,
+            ErrorKind::UnterminatedQuote,
+            ErrorKind::UnexpectedToken,
+            ErrorKind::UnbalancedBrace,
+            ErrorKind::UnbalancedBrace,
+        ]
+    );
+    assert_eq!(
+        (errors[0].position.line, errors[0].position.column),
+        (5, 20)
+    );
+    assert_eq!(errors[3].position.line, 12);
+    assert_eq!(errors[4].found, None);
+
+    let names = result.iter().map(|d| d.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["user", "http", "worker_processes", "events"]);
+    let http = result[1].children.as_ref().unwrap();
+    let names = http.iter().map(|d| d.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["server", "include"]);
+    let server = http[0].children.as_ref().unwrap();
+    assert_eq!(server.len(), 2);
+    assert_eq!(server[1].name, "root");
+    assert_eq!(result[3].children.as_ref().unwrap().len(), 1);
+    assert_eq!(result[2].span.name.line, 13);
+}
+
+#[test]
+fn recover_apache() {
+    let data = br#"
+ServerRoot "/etc/httpd"
+Listen "80
+<VirtualHost *:80>
+    ServerName example.com
+    <Directory /var/www
+    </Directory>
+    <Directory /srv>
+        Require all granted
+    </Direcotry>
+    DocumentRoot /var/www
+</VirtualHost>
+</Files>
+ServerAdmin root@localhost
+<IfModule mod_ssl.c>
+    Listen 443
+"#;
+    let (result, errors) = Directive::<Apache>::parse_recovering(data);
+    for err in &errors {
+        println!("{err}");
+    }
+    let kinds = errors.iter().map(|e| e.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            ErrorKind::UnterminatedQuote,
+            ErrorKind::UnexpectedToken,
+            ErrorKind::MismatchedCloseTag,
+            ErrorKind::MismatchedCloseTag,
+            ErrorKind::MismatchedCloseTag,
+            ErrorKind::MismatchedCloseTag,
+        ]
+    );
+    // a stray close tag is skipped
+    assert_eq!(errors[2].found.as_deref(), Some("</Direcotry>"));
+    assert_eq!(errors[2].expected, ["</Directory>"]);
+    // the close tag of an enclosing section ends the current one
+    assert_eq!(errors[3].position.line, 12);
+    assert_eq!(errors[3].found.as_deref(), Some("</VirtualHost>"));
+    assert_eq!(errors[4].position.line, 13);
+    assert_eq!(errors[5].found, None);
+
+    let names = result.iter().map(|d| d.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["ServerRoot", "VirtualHost", "ServerAdmin", "IfModule"]
+    );
+    let vhost = result[1].children.as_ref().unwrap();
+    let names = vhost.iter().map(|d| d.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["ServerName", "Directory"]);
+    assert_eq!(vhost[1].args, ["/srv"]);
+    assert_eq!(vhost[1].children.as_ref().unwrap().len(), 2);
+    assert_eq!(result[3].children.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+fn recover_valid() {
+    let data = std::fs::read("tests/nginx/few_locations.conf").unwrap();
+    let (result, errors) = Directive::<Nginx>::parse_recovering(&data);
+    assert!(errors.is_empty());
+    assert_eq!(result, Directive::<Nginx>::parse(&data).unwrap());
+}
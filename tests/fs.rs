@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use misc_conf::apache::Apache;
+use misc_conf::ast::{Config, IncludeContext};
+use misc_conf::error::IncludeError;
+use misc_conf::fs::{ConfigFs, MemFs};
+use misc_conf::ini::Ini;
+use misc_conf::nginx::Nginx;
+
+fn image() -> MemFs {
+    [
+        (
+            "etc/nginx/nginx.conf",
+            "http {\n    include conf.d/*.conf;\n}\n",
+        ),
+        (
+            "etc/nginx/conf.d/a.conf",
+            "server {\n    listen 80;\n    include snippets/*.conf;\n}\n",
+        ),
+        ("etc/nginx/conf.d/b.conf", "server {\n    listen 443;\n}\n"),
+        (
+            "etc/nginx/conf.d/sub/c.conf",
+            "server {\n    listen 8080;\n}\n",
+        ),
+        ("etc/nginx/snippets/gzip.conf", "gzip on;\n"),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[test]
+fn glob() {
+    let fs = image();
+    assert_eq!(
+        fs.glob("etc/nginx/conf.d/*.conf").unwrap(),
+        [
+            PathBuf::from("etc/nginx/conf.d/a.conf"),
+            PathBuf::from("etc/nginx/conf.d/b.conf"),
+        ]
+    );
+    assert_eq!(fs.glob("./etc/nginx/**/c.conf").unwrap().len(), 1);
+    assert!(fs.exists(Path::new("./etc/nginx/nginx.conf")));
+    assert!(!fs.exists(Path::new("etc/nginx")));
+    assert!(fs.read(Path::new("etc/passwd")).is_err());
+}
+
+#[test]
+fn nginx_in_memory() {
+    let fs = image();
+    let mut conf = Config::<Nginx>::parse_in(&fs, "etc/nginx/nginx.conf".into()).unwrap();
+    let mut ctx = IncludeContext::new("etc/nginx");
+    ctx.fs = &fs;
+    conf.resolve_include_with(&mut ctx).unwrap();
+
+    let listen = conf
+        .root
+        .query_ref("http/server/listen")
+        .iter()
+        .map(|d| d.args[0].as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(listen, ["80", "443"]);
+    let gzip = conf.root.query_ref("http/server/gzip")[0];
+    assert_eq!(gzip.span.to_string(), "etc/nginx/snippets/gzip.conf:1:1");
+
+    // nothing of it is on the disk
+    assert!(Config::<Nginx>::parse("etc/nginx/nginx.conf".into()).is_err());
+}
+
+#[test]
+fn parent_relative() {
+    let fs: MemFs = [
+        (
+            "etc/nginx/sites/a.conf",
+            "server {\n    include ../common.conf;\n}\n",
+        ),
+        ("etc/nginx/common.conf", "gzip on;\n"),
+    ]
+    .into_iter()
+    .collect();
+    assert!(fs.exists(Path::new("etc/nginx/sites/../common.conf")));
+    assert_eq!(
+        fs.glob("etc/nginx/sites/../*.conf").unwrap(),
+        [PathBuf::from("etc/nginx/common.conf")]
+    );
+
+    let mut conf = Config::<Nginx>::parse_in(&fs, "etc/nginx/sites/a.conf".into()).unwrap();
+    let mut ctx = IncludeContext::new("etc/nginx/sites");
+    ctx.fs = &fs;
+    conf.resolve_include_with(&mut ctx).unwrap();
+    assert_eq!(conf.root.query_ref("server/gzip")[0].args, ["on"]);
+}
+
+#[test]
+fn cycle_in_memory() {
+    let fs: MemFs = [
+        ("a.conf", "include b.conf;"),
+        ("b.conf", "include ./a.conf;"),
+    ]
+    .into_iter()
+    .collect();
+    let mut conf = Config::<Nginx>::parse_in(&fs, "a.conf".into()).unwrap();
+    let mut ctx = IncludeContext::new("");
+    ctx.fs = &fs;
+    let err = conf.resolve_include_with(&mut ctx).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<IncludeError>(),
+        Some(IncludeError::Cycle(chain)) if chain.len() == 3
+    ));
+}
+
+#[test]
+fn apache_in_memory() {
+    let fs: MemFs = [
+        (
+            "httpd/conf/httpd.conf",
+            "ServerRoot httpd\nIncludeOptional conf.d/*.conf\nInclude conf/extra.conf\n",
+        ),
+        ("httpd/conf.d/status.conf", "ExtendedStatus On\n"),
+        ("httpd/conf/extra.conf", "KeepAlive Off\n"),
+    ]
+    .into_iter()
+    .collect();
+    let mut conf = Config::<Apache>::parse_in(&fs, "httpd/conf/httpd.conf".into()).unwrap();
+    let mut ctx = IncludeContext::new("httpd/conf");
+    ctx.fs = &fs;
+    conf.resolve_include_with(&mut ctx).unwrap();
+    let names = conf
+        .root_directives()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["ServerRoot", "ExtendedStatus", "KeepAlive"]);
+}
+
+#[test]
+fn dropin_in_memory() {
+    let fs: MemFs = [
+        ("nginx.service", "[Service]\nType=forking\n"),
+        (
+            "nginx.service.d/limits.conf",
+            "[Service]\nLimitNOFILE=65536\n",
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let mut conf = Config::<Ini>::parse_in(&fs, "nginx.service".into()).unwrap();
+    let mut ctx = IncludeContext::new("");
+    ctx.fs = &fs;
+    conf.resolve_include_with(&mut ctx).unwrap();
+    assert_eq!(
+        conf.root.query_ref("Service/LimitNOFILE")[0].args,
+        ["65536"]
+    );
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use misc_conf::ast::*;
+use misc_conf::cpath::CPathBuf;
+use misc_conf::haproxy::Haproxy;
+
+fn parse(path: impl AsRef<Path>) -> Config<Haproxy> {
+    let path = path.as_ref();
+    println!("parsing: {:?}", path);
+    Config::<Haproxy>::parse(path.to_path_buf()).unwrap()
+}
+
+#[test]
+fn sections() {
+    let conf = parse("tests/haproxy/haproxy.cfg");
+    let names = conf
+        .root_directives()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["global", "defaults", "frontend", "backend", "backend", "listen"]
+    );
+
+    let servers = conf.root.query("backend/server");
+    assert_eq!(servers.len(), 3);
+    assert_eq!(servers[0].args, ["web1", "10.0.0.11:8080", "check"]);
+
+    let header = conf.root.query("backend/http-request").pop().unwrap();
+    assert_eq!(header.args[2], "%[uuid()] trace");
+
+    let acl = conf.root.query("frontend/http-request").pop().unwrap();
+    assert_eq!(acl.args.last().unwrap(), "}");
+}
+
+#[test]
+fn cpath() {
+    let conf = parse("tests/haproxy/haproxy.cfg");
+    let cp = CPathBuf::parse("backend[0='api']/server").unwrap();
+    let res = conf.root.cpath_query(&cp);
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].args[0], "api1");
+
+    let cp = CPathBuf::parse("//bind").unwrap();
+    assert_eq!(conf.root.cpath_query(&cp).len(), 3);
+}
+
+#[test]
+fn span() {
+    let conf = parse("tests/haproxy/haproxy.cfg");
+    let stats = conf.root.query("listen").pop().unwrap();
+    assert_eq!(stats.span.to_string(), "tests/haproxy/haproxy.cfg:40:1");
+
+    let data = std::fs::read("tests/haproxy/haproxy.cfg").unwrap();
+    let web = &conf.root_directives()[3];
+    let text = std::str::from_utf8(&data[web.span.range.clone()]).unwrap();
+    assert!(text.starts_with("backend web\n"));
+    assert!(text.ends_with("server web2 10.0.0.12:8080 check"));
+
+    let server = &web.children.as_ref().unwrap()[2];
+    assert_eq!((server.span.name.line, server.span.name.column), (32, 5));
+}
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::render::Render;
+
+    let conf = parse("tests/haproxy/haproxy.cfg");
+    let text = conf.render();
+    let reparsed = Directive::<Haproxy>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf.root_directives(), "{text}");
+    assert_eq!(reparsed.render(), text);
+}
+
+#[test]
+fn escapes() {
+    use misc_conf::render::Render;
+
+    let input = br#"log-format a\ b\#c 'd\e\' "f\"g\tx" \x41
+"#;
+    let conf = Directive::<Haproxy>::parse(input).unwrap();
+    assert_eq!(conf[0].args, ["a b#c", "d\\e\\", "f\"g\tx", "A"]);
+
+    let text = conf.render();
+    let reparsed = Directive::<Haproxy>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf, "{text}");
+
+    let conf = Directive::<Haproxy>::parse(br#"a \x7e\x7g "\x3d" '\x3d'"#).unwrap();
+    assert_eq!(conf[0].args, ["~x7g", "=", r"\x3d"]);
+    let text = conf.render();
+    assert_eq!(
+        Directive::<Haproxy>::parse(text.as_bytes()).unwrap(),
+        conf,
+        "{text}"
+    );
+
+    // names are quoted like arguments
+    let conf = vec![Directive::<Haproxy>::new(" ", ["x"])];
+    let text = conf.render();
+    assert_eq!(text, "\" \" x\n");
+    assert_eq!(Directive::<Haproxy>::parse(text.as_bytes()).unwrap(), conf);
+}
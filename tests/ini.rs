@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use misc_conf::ast::*;
+use misc_conf::cpath::CPathBuf;
+use misc_conf::ini::{self, Ini};
+
+fn parse(path: impl AsRef<Path>) -> Config<Ini> {
+    let path = path.as_ref();
+    println!("parsing: {:?}", path);
+    Config::<Ini>::parse(path.to_path_buf()).unwrap()
+}
+
+#[test]
+fn sections() {
+    let conf = parse("tests/ini/nginx.service");
+    let root = conf.root_directives();
+    let names = root.iter().map(|d| d.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["Unit", "Service", "Install"]);
+
+    let pre = conf.root.query("Service/ExecStartPre").pop().unwrap();
+    assert_eq!(
+        pre.args,
+        ["/usr/sbin/nginx -t -q      -g 'daemon on; master_process on;'"]
+    );
+    assert_eq!(pre.span.args[0].line, 10);
+    assert_eq!(pre.span.args[0].column, 14);
+
+    let timeout = conf.root.query("Service/TimeoutStopSec").pop().unwrap();
+    assert_eq!(timeout.args, ["5"]);
+    let reset = conf.root.query("Service/Environment").pop().unwrap();
+    assert!(reset.args.is_empty());
+
+    let cp = CPathBuf::parse("Unit/After").unwrap();
+    assert_eq!(conf.root.cpath_query(&cp).len(), 2);
+
+    let data = std::fs::read("tests/ini/nginx.service").unwrap();
+    let text = std::str::from_utf8(&data[root[1].span.range.clone()]).unwrap();
+    assert!(text.starts_with("[Service]"));
+    assert!(text.ends_with("Environment="));
+}
+
+#[test]
+fn repeated_keys() {
+    let conf = parse("tests/ini/nginx.service");
+    let root = conf.root_directives();
+    assert_eq!(
+        ini::values(root, "Unit", "After"),
+        [
+            "network-online.target remote-fs.target",
+            "nss-lookup.target"
+        ]
+    );
+    // reset by the empty assignment
+    assert!(ini::values(root, "Service", "Environment").is_empty());
+    assert_eq!(ini::value(root, "Service", "Type"), Some("forking"));
+    assert_eq!(ini::value(root, "Service", "User"), None);
+}
+
+#[test]
+fn dropin() {
+    let mut conf = parse("tests/ini/nginx.service");
+    conf.resolve_include(None, None).unwrap();
+    let root = conf.root_directives();
+    assert_eq!(root.len(), 3);
+    assert_eq!(
+        ini::values(root, "Service", "Environment"),
+        ["NGINX_WORKERS=4"]
+    );
+    assert_eq!(ini::value(root, "Service", "TimeoutStopSec"), Some("10"));
+
+    let limit = conf.root.query("Service/LimitNOFILE").pop().unwrap();
+    assert_eq!(
+        limit.span.to_string(),
+        "tests/ini/nginx.service.d/20-limits.conf:2:1"
+    );
+    let site = limit.span.included.as_ref().unwrap();
+    assert_eq!(site.name, "nginx.service.d");
+    assert_eq!(site.args, ["*.conf"]);
+    assert_eq!(
+        site.span.path.as_deref(),
+        Some(Path::new("tests/ini/nginx.service"))
+    );
+    let kind = conf.root.query_ref("Service/Type")[0];
+    assert!(kind.span.included.is_none());
+}
+
+#[test]
+fn keep_dropin() {
+    let mut ctx = IncludeContext::new("tests/ini");
+    ctx.keep_includes = true;
+    let mut conf = parse("tests/ini/nginx.service");
+    let text = conf.render();
+    conf.resolve_include_with(&mut ctx).unwrap();
+
+    let root = conf.root_directives();
+    assert_eq!(root.len(), 3);
+    let service = conf.root.query_ref("Service").pop().unwrap();
+    let kept = service.children.as_ref().unwrap();
+    let kept = kept.iter().filter(|d| d.transparent).collect::<Vec<_>>();
+    // one for the section of each drop-in file
+    assert_eq!(kept.len(), 2);
+    assert_eq!(kept[0].name, "nginx.service.d");
+    assert_eq!(kept[0].children.as_ref().unwrap()[0].name, "ProtectSystem");
+    assert_eq!(kept[1].children.as_ref().unwrap()[0].name, "LimitNOFILE");
+
+    // the values and queries see through the kept drop-in, which isn't rendered
+    assert_eq!(
+        ini::values(root, "Service", "Environment"),
+        ["NGINX_WORKERS=4"]
+    );
+    assert_eq!(ini::value(root, "Service", "TimeoutStopSec"), Some("10"));
+    assert_eq!(conf.root.query_ref("Service/LimitNOFILE").len(), 1);
+    assert_eq!(conf.render(), text);
+}
+
+#[test]
+fn dropin_context() {
+    use misc_conf::error::IncludeError;
+
+    let mut ctx = IncludeContext::new("tests/ini");
+    ctx.max_depth = 0;
+    let mut conf = parse("tests/ini/nginx.service");
+    let err = conf.resolve_include_with(&mut ctx).unwrap_err();
+    let Some(IncludeError::TooDeep(0, chain)) = err.downcast_ref::<IncludeError>() else {
+        panic!("{err:?}");
+    };
+    assert_eq!(chain[0], Path::new("tests/ini/nginx.service"));
+    assert_eq!(
+        chain[1],
+        Path::new("tests/ini/nginx.service.d/10-hardening.conf")
+    );
+}
+
+#[test]
+fn errors() {
+    use misc_conf::error::{ErrorKind, ParseError};
+
+    let err = Directive::<Ini>::parse(b"[Unit\nA=b\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnexpectedToken);
+    assert_eq!(err.expected, ["]"]);
+
+    let err = Directive::<Ini>::parse(b"[Unit]\nDescription\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.expected, ["="]);
+    assert_eq!(err.position.line, 2);
+}
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::render::Render;
+
+    let conf = parse("tests/ini/nginx.service");
+    let text = conf.render();
+    let reparsed = Directive::<Ini>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf.root_directives(), "{text}");
+    assert_eq!(reparsed.render(), text);
+}
+
+#[test]
+fn dangling_continuation() {
+    use misc_conf::render::Render;
+
+    for input in ["[Unit]\nA=b \\", "[Unit]\nA=b \\\r", "[Unit]\nA=b \\\n"] {
+        let conf = Directive::<Ini>::parse(input.as_bytes()).unwrap();
+        assert_eq!(ini::value(&conf, "Unit", "A"), Some("b"), "{input:?}");
+        let text = conf.render();
+        assert_eq!(text, "[Unit]\nA=b\n");
+        assert_eq!(Directive::<Ini>::parse(text.as_bytes()).unwrap(), conf);
+    }
+}
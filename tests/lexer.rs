@@ -0,0 +1,64 @@
+use std::borrow::Cow;
+
+use misc_conf::lexer::{Escapes, Literal};
+
+fn lit(raw: &str, quote: u8) -> Literal<'_> {
+    Literal {
+        raw,
+        quote,
+        escapes: Escapes::Common,
+    }
+}
+
+#[test]
+fn unescape_borrows() {
+    assert!(matches!(lit("plain", 0).unescape(), Cow::Borrowed("plain")));
+    assert!(matches!(
+        lit(r"a\b", b'`').unescape(),
+        Cow::Borrowed(r"a\b")
+    ));
+    assert_eq!(String::from(lit("", b'"')), "");
+}
+
+#[test]
+fn unescape() {
+    assert_eq!(lit(r"a\\b", 0).unescape(), r"a\b");
+    assert_eq!(lit(r#"say \"hi\""#, b'"').unescape(), r#"say "hi""#);
+    assert_eq!(lit(r"it\'s", b'\'').unescape(), "it's");
+    // the other quote is kept escaped
+    assert_eq!(lit(r"it\'s", b'"').unescape(), r"it\'s");
+    assert_eq!(lit("a\\\nb", 0).unescape(), "a\nb");
+    // unknown escapes and a trailing backslash are kept
+    assert_eq!(lit(r"\.ht\", 0).unescape(), r"\.ht\");
+    // in one pass, `\\\"` is a backslash and a quote
+    assert_eq!(lit(r#"\\\""#, b'"').unescape(), r#"\""#);
+    assert_eq!(lit(r#"\\\""#, b'"').to_string(), r#"\""#);
+}
+
+#[test]
+fn unescape_nginx() {
+    let nginx = |raw, quote| Literal {
+        raw,
+        quote,
+        escapes: Escapes::Nginx,
+    };
+    assert_eq!(nginx(r"\.php$", 0).unescape(), r"\.php$");
+    assert_eq!(nginx(r"a\tb\n", 0).unescape(), "a\tb\n");
+    assert_eq!(nginx(r#"\"\'\\"#, 0).unescape(), r#""'\"#);
+    // no line continuation in nginx
+    assert_eq!(nginx("a\\\nb", b'"').unescape(), "a\\\nb");
+}
+
+#[test]
+fn unescape_apache() {
+    let apache = |raw, quote| Literal {
+        raw,
+        quote,
+        escapes: Escapes::Apache,
+    };
+    assert_eq!(apache(r#"\"%r\" \\"#, b'"').unescape(), r#""%r" \"#);
+    assert_eq!(apache(r"\.ht*", 0).unescape(), r"\.ht*");
+    // line continuations are dropped, with trailing whitespace and CR
+    assert_eq!(apache("abc\\\ndef", 0).unescape(), "abcdef");
+    assert_eq!(apache("one \\ \t\r\ntwo", b'"').unescape(), "one two");
+}
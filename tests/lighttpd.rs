@@ -0,0 +1,185 @@
+use std::path::Path;
+
+use misc_conf::ast::*;
+use misc_conf::cpath::CPathBuf;
+use misc_conf::lighttpd::{unquote, Lighttpd};
+use misc_conf::render::Render;
+
+fn parse(path: impl AsRef<Path>) -> Config<Lighttpd> {
+    let path = path.as_ref();
+    println!("parsing: {:?}", path);
+    Config::<Lighttpd>::parse(path.to_path_buf()).unwrap()
+}
+
+#[test]
+fn assignments() {
+    let conf = parse("tests/lighttpd/lighttpd.conf");
+    let modules = conf.root.query("server.modules");
+    assert_eq!(modules.len(), 2);
+    assert_eq!(modules[0].args, ["="]);
+    let elements = modules[0].children.as_ref().unwrap();
+    assert_eq!(elements.len(), 2);
+    assert_eq!(elements[1].name, "");
+    assert_eq!(elements[1].args, [r#""mod_rewrite""#]);
+    assert_eq!(modules[1].args, ["+="]);
+
+    let root = conf.root.query("server.document-root").pop().unwrap();
+    assert_eq!(root.args, ["=", "var.basedir", r#""/htdocs""#]);
+    let port = conf.root.query("server.port").pop().unwrap();
+    assert_eq!(port.args, ["=", "80"]);
+    let tag = conf.root.query("server.tag").pop().unwrap();
+    assert_eq!(tag.args, [":=", r#""lighttpd""#]);
+    assert_eq!(unquote(&tag.args[1]), "lighttpd");
+
+    let css = conf.root.query("mimetype.assign/.css").pop().unwrap();
+    assert_eq!(css.args, ["=>", r#""text/css""#]);
+    assert_eq!(css.span.name.line, 17);
+    assert_eq!(css.span.args[1].column, 16);
+}
+
+#[test]
+fn conditionals() {
+    let conf = parse("tests/lighttpd/lighttpd.conf");
+    let ssl = conf
+        .root
+        .query(r#"$SERVER["socket"]/ssl.engine"#)
+        .pop()
+        .unwrap();
+    assert_eq!(ssl.args, ["=", r#""enable""#]);
+
+    // names in cpath are regex
+    let cp = CPathBuf::parse(r#"'\$HTTP\["host"\]'[1='example.com']//url.rewrite-once"#).unwrap();
+    let rewrite = conf.root.cpath_query(&cp).pop().unwrap();
+    assert_eq!(rewrite.args, ["="]);
+    let rule = &rewrite.children.as_ref().unwrap()[0];
+    assert_eq!(rule.name, "^/old/(.*)");
+    assert_eq!(rule.args, ["=>", r#""/new/$1""#]);
+
+    let elses = conf.root.query("else");
+    assert_eq!(elses.len(), 2);
+    assert_eq!(elses[0].args, [r#"$HTTP["host"]"#, "=~", r"^www\."]);
+    assert!(elses[1].args.is_empty());
+    assert_eq!(elses[1].children.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+fn include() {
+    let mut conf = parse("tests/lighttpd/lighttpd.conf");
+    conf.resolve_include(None, None).unwrap();
+    let modules = conf.root.query("server.modules");
+    assert_eq!(modules.len(), 3);
+    assert_eq!(modules[2].args, ["+="]);
+    assert_eq!(
+        modules[2].children.as_ref().unwrap()[0].args,
+        [r#""mod_status""#]
+    );
+    assert_eq!(
+        modules[2].span.to_string(),
+        "tests/lighttpd/conf.d/status.conf:1:1"
+    );
+
+    // never executed
+    let shell = conf.root.query("include_shell").pop().unwrap();
+    assert_eq!(shell.args, [r#""cat /etc/lighttpd/extra/*.conf""#]);
+}
+
+#[test]
+fn errors() {
+    use misc_conf::error::{ErrorKind, ParseError};
+
+    let err = Directive::<Lighttpd>::parse(b"server.port 80\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnexpectedToken);
+
+    let err = Directive::<Lighttpd>::parse(b"$HTTP[\"host\"] == \"x\" {\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnbalancedBrace);
+
+    // statements which can't be written back
+    for input in [
+        "key == \"a\"\n",
+        "key { a = 1 }\n",
+        "$HTTP[\"host\"] = \"x\" { }\n",
+        "$HTTP[\"host\"] == x { }\n",
+        "x = ( 1 => 2 )\n",
+    ] {
+        let err = Directive::<Lighttpd>::parse(input.as_bytes()).unwrap_err();
+        let err = err.downcast_ref::<ParseError>().unwrap();
+        assert_eq!(err.kind, ErrorKind::UnexpectedToken, "{input}");
+    }
+}
+
+#[test]
+fn statements() {
+    for input in [
+        "a = \"x\"\n",
+        "a += var.b + \"/c\" + 8\n",
+        "a := 80\n",
+        "a = \"80\"\n",
+        "a = \"var.b\"\n",
+        "a = ( \"k\" => ( \"b\", 1 ), \"v\" )\n",
+        "$HTTP[\"host\"] == \"x\" {\n    a = 1\n}\nelse $SERVER[\"socket\"] != \":80\" {\n}\nelseif $HTTP[\"url\"] =^ \"/a\" {\n}\nelse {\n}\n",
+        "include \"a.conf\"\n",
+        "include_shell \"cat a.conf\"\n",
+    ] {
+        let conf = Directive::<Lighttpd>::parse(input.as_bytes()).unwrap();
+        let text = conf.render();
+        assert_eq!(
+            Directive::<Lighttpd>::parse(text.as_bytes()).unwrap(),
+            conf,
+            "{text}"
+        );
+    }
+
+    // strings and words differ
+    let conf = Directive::<Lighttpd>::parse(b"a = \"80\"\nb = 80\nc = \"var.x\"\n").unwrap();
+    assert_eq!(conf.render(), "a = \"80\"\nb = 80\nc = \"var.x\"\n");
+}
+
+#[test]
+fn arrays() {
+    let input = br#"x = ( "a" => ( "b" => 1, "c" + var.d ), ( "e" ), "=>" , "" => "f" )
+y = ()
+"#;
+    let conf = Directive::<Lighttpd>::parse(input).unwrap();
+    let elements = conf[0].children.as_ref().unwrap();
+    assert_eq!(elements.len(), 4);
+    assert_eq!(elements[0].name, "a");
+    assert_eq!(elements[0].args, ["=>"]);
+    let nested = elements[0].children.as_ref().unwrap();
+    assert_eq!(nested[0].name, "b");
+    assert_eq!(nested[0].args, ["=>", "1"]);
+    assert_eq!(nested[1].name, "");
+    assert_eq!(nested[1].args, [r#""c""#, "var.d"]);
+    assert_eq!(elements[1].args, Vec::<String>::new());
+    assert_eq!(elements[1].children.as_ref().unwrap()[0].args, [r#""e""#]);
+    assert_eq!(elements[2].args, [r#""=>""#]);
+    assert_eq!(elements[3].args, ["=>", r#""f""#]);
+    assert_eq!(conf[1].children, Some(vec![]));
+
+    let text = conf.render();
+    assert_eq!(
+        Directive::<Lighttpd>::parse(text.as_bytes()).unwrap(),
+        conf,
+        "{text}"
+    );
+
+    let err = Directive::<Lighttpd>::parse(b"x = ( \"a\" + \"b\" => 1 )").unwrap_err();
+    let err = err.downcast_ref::<misc_conf::error::ParseError>().unwrap();
+    assert_eq!(err.kind, misc_conf::error::ErrorKind::UnexpectedToken);
+}
+
+#[test]
+fn render_roundtrip() {
+    let conf = parse("tests/lighttpd/lighttpd.conf");
+    let text = conf.render();
+    let reparsed = Directive::<Lighttpd>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf.root_directives(), "{text}");
+    assert_eq!(reparsed.render(), text);
+    assert!(text.contains("server.document-root = var.basedir + \"/htdocs\"\n"));
+    assert!(text.contains("    \".css\" => \"text/css\",\n"));
+    assert!(
+        text.contains(r#"else $HTTP["host"] =~ "^www\." {"#),
+        "{text}"
+    );
+}
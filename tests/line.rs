@@ -0,0 +1,120 @@
+use misc_conf::ast::*;
+use misc_conf::error::{ErrorKind, ParseError};
+use misc_conf::line::*;
+use misc_conf::render::Render;
+
+#[derive(Debug, Clone, Default)]
+struct Postfix;
+
+impl LineScheme for Postfix {
+    const SYNTAX: LineSyntax = LineSyntax::new()
+        .separator(b'=')
+        .continuation(Continuation::Indent);
+}
+
+#[derive(Debug, Clone, Default)]
+struct Blocks;
+
+impl LineScheme for Blocks {
+    const SYNTAX: LineSyntax = LineSyntax::new()
+        .comment(b';', true)
+        .continuation(Continuation::Backslash)
+        .quotes(b"\"'")
+        .sections(&["[server]"])
+        .blocks(&[("if", "endif"), ("loop", "end")]);
+}
+
+#[test]
+fn postfix() {
+    let conf = Config::<Postfix>::parse("tests/line/main.cf".into()).unwrap();
+    let root = conf.root_directives();
+    assert_eq!(root.len(), 5);
+    assert_eq!(root[0].args, ["mail.example.com"]);
+    assert_eq!(
+        root[2].args,
+        ["$myhostname,", "localhost.$mydomain,", "localhost"]
+    );
+    assert_eq!(root[2].span.args[2].line, 5);
+    assert!(root[3].args.is_empty());
+    assert_eq!(root[4].name, "smtpd_recipient_restrictions");
+    assert_eq!(root[4].args.len(), 2);
+
+    let text = conf.render();
+    let reparsed = Directive::<Postfix>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, root, "{text}");
+}
+
+#[test]
+fn backslash() {
+    // postfix doesn't interpret backslashes
+    let conf = Directive::<Postfix>::parse(br#"key = a\\b "c\\d" e\f"#).unwrap();
+    assert_eq!(conf[0].args, [r"a\\b", r"c\\d", r"e\f"]);
+
+    let text = conf.render();
+    assert_eq!(
+        Directive::<Postfix>::parse(text.as_bytes()).unwrap(),
+        conf,
+        "{text}"
+    );
+}
+
+#[test]
+fn indented_comment() {
+    let input = b"a = b,\n    # note\n    c\nd = e\n    # trailing\nf = g\n";
+    let conf = Directive::<Postfix>::parse(input).unwrap();
+    assert_eq!(conf.len(), 3);
+    assert_eq!(conf[0].args, ["b,", "c"]);
+    assert_eq!(conf[1].args, ["e"]);
+    assert_eq!(conf[2].name, "f");
+}
+
+#[test]
+fn quoted_names() {
+    let conf = ["a b", "'", ";x", "\""]
+        .into_iter()
+        .map(|name| Directive::<Blocks>::new(name, ["1"]))
+        .collect::<Vec<_>>();
+    let text = conf.render();
+    let reparsed = Directive::<Blocks>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf, "{text}");
+}
+
+#[test]
+fn blocks() {
+    let input = b"name 'a b' ; comment\n\
+        if x \\\n  = 1\n  loop 3\n    step;no space\n  end\nendif\n\
+        [server] one\nport 80\n[server] two\nport 81\n";
+    let conf = Directive::<Blocks>::parse(input).unwrap();
+    assert_eq!(conf.len(), 4);
+    assert_eq!(conf[0].args, ["a b"]);
+    assert_eq!(conf[1].name, "if");
+    assert_eq!(conf[1].args, ["x", "=", "1"]);
+    let step = conf[1].query("loop/step").pop().unwrap();
+    assert_eq!(step.name, "step");
+    assert!(step.args.is_empty());
+    assert_eq!(conf[3].args, ["two"]);
+    assert_eq!(conf[3].query("port")[0].args, ["81"]);
+
+    let text = conf.render();
+    let reparsed = Directive::<Blocks>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf, "{text}");
+    assert_eq!(reparsed.render(), text);
+}
+
+#[test]
+fn errors() {
+    let err = Directive::<Blocks>::parse(b"if a\nloop\nend\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnexpectedEof);
+    assert_eq!(err.expected, ["endif"]);
+
+    let err = Directive::<Blocks>::parse(b"if a\nend\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnexpectedToken);
+    assert_eq!(err.expected, ["endif"]);
+    assert_eq!(err.position.line, 2);
+
+    let err = Directive::<Blocks>::parse(b"if a\n[server] x\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnexpectedToken);
+}
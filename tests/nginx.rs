@@ -35,6 +35,158 @@ fn include() {
     assert_eq!(conf.root, conf2.root);
 }
 
+#[test]
+fn include_prefix() {
+    use misc_conf::nginx::include_context;
+
+    let path = "tests/nginx/prefix/conf/nginx.conf";
+    let mut ctx = include_context(
+        Path::new("tests/nginx/prefix"),
+        Path::new("conf/nginx.conf"),
+    );
+    assert_eq!(ctx.root, Path::new("tests/nginx/prefix/conf"));
+
+    let mut conf = parse(path);
+    conf.resolve_include_with(&mut ctx).unwrap();
+    // the nested include is relative to the conf directory too
+    let ssl = conf.root.query("http/server/ssl_protocols");
+    assert_eq!(ssl[0].args, ["TLSv1.2", "TLSv1.3"]);
+    assert!(ssl[0].span.to_string().ends_with("snippets/ssl.conf:1:1"));
+
+    let mut conf2 = parse(path);
+    conf2.resolve_include(None, None).unwrap();
+    assert_eq!(conf.root, conf2.root);
+}
+
+#[test]
+fn include_trace() {
+    let mut conf = parse("tests/nginx/prefix/conf/nginx.conf");
+    conf.resolve_include(None, None).unwrap();
+
+    let name = conf.root.query_ref("http/server/server_name")[0];
+    let trace = name.span.include_trace().collect::<Vec<_>>();
+    assert_eq!(trace.len(), 1);
+    assert_eq!(trace[0].args, ["sites/*.conf"]);
+    assert_eq!(
+        trace[0].span.to_string(),
+        "tests/nginx/prefix/conf/nginx.conf:2:5"
+    );
+
+    // from the innermost include
+    let ssl = conf.root.query_ref("http/server/ssl_protocols")[0];
+    let trace = ssl
+        .span
+        .include_trace()
+        .map(|i| (i.args[0].as_str(), i.span.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        trace,
+        [
+            (
+                "snippets/ssl.conf",
+                "tests/nginx/prefix/conf/sites/example.conf:4:5".into()
+            ),
+            (
+                "sites/*.conf",
+                "tests/nginx/prefix/conf/nginx.conf:2:5".into()
+            ),
+        ]
+    );
+    assert!(conf.root.query_ref("http")[0].span.included.is_none());
+}
+
+#[test]
+fn keep_includes() {
+    use misc_conf::ast::IncludeContext;
+    use misc_conf::cpath::CPathBuf;
+    use misc_conf::render::Render;
+
+    let path = "tests/nginx/prefix/conf/nginx.conf";
+    let mut ctx = IncludeContext::new("tests/nginx/prefix/conf");
+    ctx.keep_includes = true;
+    let mut conf = parse(path);
+    conf.resolve_include_with(&mut ctx).unwrap();
+
+    let http = conf.root.query_ref("http")[0];
+    let include = &http.children.as_ref().unwrap()[0];
+    assert!(include.transparent);
+    assert_eq!(include.args, ["sites/*.conf"]);
+    assert_eq!(include.children.as_ref().unwrap()[0].name, "server");
+
+    // the queries see through the kept includes
+    assert_eq!(conf.root.query_ref("http/server/ssl_protocols").len(), 1);
+    let path_ssl = CPathBuf::parse("//ssl_protocols").unwrap();
+    let matches = conf.root.cpath_matches(&path_ssl);
+    let ancestors = matches[0]
+        .ancestors
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(ancestors, ["http", "server"]);
+    assert_eq!(conf.root.query_mut("http/server/server_name").len(), 1);
+
+    // rendered as written
+    assert_eq!(conf.render(), parse(path).root_directives().render(),);
+
+    assert_eq!(conf.root.cpath_remove(&path_ssl).len(), 1);
+    assert_eq!(conf.root.remove("http/server/server_name").len(), 1);
+    assert!(conf.root.query_ref("http/server/server_name").is_empty());
+}
+
+#[test]
+fn include_cycle() {
+    use misc_conf::ast::IncludeContext;
+    use misc_conf::error::IncludeError;
+
+    let mut conf = parse("tests/nginx/cycle/a.conf");
+    let err = conf
+        .resolve_include_with(&mut IncludeContext::new("tests/nginx"))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<IncludeError>(),
+        Some(&IncludeError::Cycle(vec![
+            "tests/nginx/cycle/a.conf".into(),
+            "tests/nginx/cycle/b.conf".into(),
+            "tests/nginx/cycle/a.conf".into(),
+        ]))
+    );
+    assert_eq!(
+        err.to_string(),
+        "include cycle: tests/nginx/cycle/a.conf -> tests/nginx/cycle/b.conf -> tests/nginx/cycle/a.conf"
+    );
+
+    // the glob matches the including file
+    let mut conf = parse("tests/nginx/cycle/self.conf");
+    let err = conf.resolve_include(None, None).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<IncludeError>(),
+        Some(IncludeError::Cycle(chain)) if chain.len() == 2
+    ));
+}
+
+#[test]
+fn include_depth() {
+    use misc_conf::ast::IncludeContext;
+    use misc_conf::error::IncludeError;
+
+    let mut ctx = IncludeContext::new("tests/nginx/prefix/conf");
+    ctx.max_depth = 1;
+    let mut conf = parse("tests/nginx/prefix/conf/nginx.conf");
+    let err = conf.resolve_include_with(&mut ctx).unwrap_err();
+    match err.downcast_ref::<IncludeError>() {
+        Some(IncludeError::TooDeep(1, chain)) => {
+            assert!(chain.last().unwrap().ends_with("snippets/ssl.conf"));
+        }
+        _ => panic!("{err}"),
+    }
+    assert!(ctx.chain().is_empty());
+
+    ctx.max_depth = 2;
+    let mut conf = parse("tests/nginx/prefix/conf/nginx.conf");
+    conf.resolve_include_with(&mut ctx).unwrap();
+    assert_eq!(conf.root.query("http/server/ssl_protocols").len(), 1);
+}
+
 #[test]
 fn lua() {
     let conf = parse("tests/nginx/lua.conf");
@@ -43,3 +195,153 @@ fn lua() {
     assert_eq!(d.args[0], "ocsp_response_cache");
     assert_eq!(d.args[1], "5M");
 }
+
+#[test]
+fn lua_block() {
+    use misc_conf::ast::*;
+
+    let conf = parse("tests/nginx/lua.conf");
+    let d = conf.root.query("http/init_by_lua_block").pop().unwrap();
+    assert!(d.children.is_none());
+    let raw = d.raw_block.as_ref().unwrap();
+    assert!(raw
+        .source
+        .trim_start()
+        .starts_with("collectgarbage(\"collect\")"));
+    assert!(raw
+        .source
+        .trim_end()
+        .ends_with("lua_ingress.set_config({})"));
+    assert_eq!((raw.start.line, raw.start.column), (24, 21));
+    let data = std::fs::read("tests/nginx/lua.conf").unwrap();
+    assert_eq!(&data[raw.range.clone()], raw.source.as_bytes());
+
+    let data = br#"
+    content_by_lua_block {
+        local t = { a = 1 }
+        ngx.say(t.a)
+    }
+    "#;
+    let d = Directive::<Nginx>::parse(data).unwrap().pop().unwrap();
+    let raw = d.raw_block.as_ref().unwrap();
+    assert_eq!(raw.parse_lua().unwrap().statements.len(), 2);
+
+    // braces in strings and comments, and many blocks
+    let block = r#"
+    access_by_lua_block {
+        local s = "}" .. '{' .. "\"}"
+        -- } in a comment
+        --[==[ } ]] } ]==]
+        local l = [[ { ]] .. [=[ } ]=]
+        if s then ngx.say(s) end
+    }
+    "#;
+    let data = block.repeat(200);
+    let list = Directive::<Nginx>::parse(data.as_bytes()).unwrap();
+    assert_eq!(list.len(), 200);
+    let raw = list[199].raw_block.as_ref().unwrap();
+    assert_eq!(&data[raw.range.clone()], raw.source);
+    assert_eq!(raw.parse_lua().unwrap().statements.len(), 3);
+    assert!(raw.source.trim_end().ends_with("end"));
+
+    assert!(Directive::<Nginx>::parse(b"content_by_lua_block { local s = '}").is_err());
+    assert!(Directive::<Nginx>::parse(b"content_by_lua_block { --[[ } ").is_err());
+}
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::ast::*;
+    use misc_conf::render::Render;
+
+    for path in glob::glob("tests/nginx/**/*.conf").unwrap().flatten() {
+        let conf = parse(&path);
+        let text = conf.render();
+        let reparsed = Directive::<Nginx>::parse(text.as_bytes())
+            .unwrap_or_else(|e| panic!("reparse {path:?}: {e}\n{text}"));
+        assert_eq!(reparsed, conf.root_directives(), "{path:?}\n{text}");
+        assert_eq!(reparsed.render(), text);
+    }
+}
+
+#[test]
+fn span() {
+    let conf = parse("tests/nginx/few_locations.conf");
+    let listen = conf.root.query("http/server/listen").pop().unwrap();
+    assert_eq!((listen.span.name.line, listen.span.name.column), (3, 9));
+    let cols = listen
+        .span
+        .args
+        .iter()
+        .map(|p| p.column)
+        .collect::<Vec<_>>();
+    assert_eq!(cols, [16, 19, 23]);
+    assert_eq!(
+        listen.span.to_string(),
+        "tests/nginx/few_locations.conf:3:9"
+    );
+
+    let data = std::fs::read("tests/nginx/few_locations.conf").unwrap();
+    let server = conf.root.query("http/server").pop().unwrap();
+    let text = std::str::from_utf8(&data[server.span.range.clone()]).unwrap();
+    assert!(text.starts_with("server {"));
+    assert!(text.ends_with('}'));
+
+    let mut conf = parse("tests/nginx/include.conf");
+    conf.resolve_include(None, None).unwrap();
+    let index = &conf.root_directives()[1];
+    assert_eq!(index.span.to_string(), "tests/nginx/index.conf:2:1");
+}
+
+#[test]
+fn borrowed() {
+    use misc_conf::ast::*;
+    use misc_conf::lexer::Literal;
+
+    fn owned(d: &Directive<Nginx, Literal>) -> Directive<Nginx> {
+        let mut o = Directive::new(d.name, d.args.iter().copied());
+        o.children = d.children.as_ref().map(|c| c.iter().map(owned).collect());
+        o.raw_block = d.raw_block.clone();
+        o
+    }
+
+    for path in glob::glob("tests/nginx/**/*.conf").unwrap().flatten() {
+        let data = std::fs::read(&path).unwrap();
+        let borrowed = misc_conf::nginx::parse::<Literal>(&data).unwrap();
+        let owned = borrowed.iter().map(owned).collect::<Vec<_>>();
+        assert_eq!(owned, Directive::<Nginx>::parse(&data).unwrap(), "{path:?}");
+    }
+
+    let data = std::fs::read("tests/nginx/few_locations.conf").unwrap();
+    let mut root = Directive::block(Literal::default(), Vec::<Literal>::new());
+    *root.children_mut() = misc_conf::nginx::parse::<Literal>(&data).unwrap();
+    let listen = root.query_ref("http/server/listen").pop().unwrap();
+    assert_eq!(listen.args[0].raw, "80");
+    assert!(data.as_ptr_range().contains(&listen.name.raw.as_ptr()));
+}
+
+#[test]
+fn escapes() {
+    use misc_conf::ast::*;
+    use misc_conf::render::Render;
+
+    let data = br#"
+    location ~ \.php$ {
+        return 200 "a\tb\n\"c\" \d";
+        add_header X-Path \\server\share;
+        set $quote 'it\'s';
+    }
+    "#;
+    let d = Directive::<Nginx>::parse(data).unwrap().pop().unwrap();
+    assert_eq!(d.args, ["~", r"\.php$"]);
+    let res = d.query("return").pop().unwrap();
+    assert_eq!(res.args[1], "a\tb\n\"c\" \\d");
+    let res = d.query("add_header").pop().unwrap();
+    assert_eq!(res.args[1], r"\server\share");
+    let res = d.query("set").pop().unwrap();
+    assert_eq!(res.args[1], "it's");
+
+    let text = d.render();
+    assert!(text.starts_with(r"location ~ \.php$ {"), "{text}");
+    let reparsed = Directive::<Nginx>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, [d], "{text}");
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use misc_conf::ast::*;
+use misc_conf::cpath::CPathBuf;
+use misc_conf::redis::{renamed_commands, Redis};
+
+fn parse(path: impl AsRef<Path>) -> Config<Redis> {
+    let path = path.as_ref();
+    println!("parsing: {:?}", path);
+    Config::<Redis>::parse(path.to_path_buf()).unwrap()
+}
+
+#[test]
+fn directives() {
+    let conf = parse("tests/redis/redis.conf");
+    let root = conf.root_directives();
+    assert_eq!(root.len(), 9);
+    assert_eq!(root[0].args, ["127.0.0.1", "-::1"]);
+    assert_eq!(root[3].args, ["s3cr\"et!"]);
+    assert_eq!(root[4].span.name.line, 7);
+    assert_eq!(root[4].args, ["it's"]);
+    assert_eq!(root[5].args, ["Ex\n"]);
+
+    let cp = CPathBuf::parse("protected-mode[0='yes']").unwrap();
+    assert_eq!(conf.root.cpath_query(&cp).len(), 1);
+}
+
+#[test]
+fn include() {
+    let mut conf = parse("tests/redis/redis.conf");
+    conf.resolve_include(None, None).unwrap();
+    let policy = conf.root.query("maxmemory-policy").pop().unwrap();
+    assert_eq!(policy.args, ["allkeys-lru"]);
+    assert_eq!(
+        policy.span.to_string(),
+        "tests/redis/conf.d/memory.conf:2:1"
+    );
+
+    let renames = renamed_commands(conf.root_directives());
+    assert_eq!(renames["flushall"], "");
+    // renamed again by the included file
+    assert_eq!(renames["config"], "");
+}
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::render::Render;
+
+    let conf = parse("tests/redis/redis.conf");
+    let text = conf.render();
+    assert!(
+        text.contains("notify-keyspace-events \"Ex\\n\"\n"),
+        "{text}"
+    );
+    let reparsed = Directive::<Redis>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf.root_directives(), "{text}");
+    assert_eq!(reparsed.render(), text);
+}
@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use misc_conf::ast::*;
+use misc_conf::cpath::CPathBuf;
+use misc_conf::squid::Squid;
+
+fn parse(path: impl AsRef<Path>) -> Config<Squid> {
+    let path = path.as_ref();
+    println!("parsing: {:?}", path);
+    Config::<Squid>::parse(path.to_path_buf()).unwrap()
+}
+
+#[test]
+fn directives() {
+    let conf = parse("tests/squid/squid.conf");
+    let acls = conf.root.query("acl");
+    assert_eq!(acls.len(), 5);
+    assert_eq!(
+        acls[3].args,
+        ["Safe_ports", "port", "80", "443", "8080", "8443"]
+    );
+    assert_eq!(acls[3].span.args[4].line, 6);
+    assert_eq!(
+        acls[4].args,
+        [
+            "blocked",
+            "url_regex",
+            "-i",
+            r"\.exe$",
+            "\"/etc/squid/blocked words\""
+        ]
+    );
+
+    let cp = CPathBuf::parse("http_access[0='deny']").unwrap();
+    assert_eq!(conf.root.cpath_query(&cp).len(), 3);
+
+    let logformat = conf.root.query("logformat").pop().unwrap();
+    assert_eq!(logformat.args[1], "%>a");
+    // quotes are part of the format
+    assert_eq!(logformat.args[5], "\"%rm %ru HTTP/%rv\"");
+}
+
+#[test]
+fn conditionals() {
+    let conf = parse("tests/squid/squid.conf");
+    let cond = conf.root.query("if").pop().unwrap();
+    assert_eq!(cond.args, ["${process_number}", "=", "1"]);
+    let names = cond
+        .children
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["cache_dir", "if", "else"]);
+    let port = conf.root.query("if/if/http_port").pop().unwrap();
+    assert_eq!(port.args, ["3129"]);
+    let null = conf.root.query("if/else/cache_dir").pop().unwrap();
+    assert_eq!(null.args, ["null", "/tmp"]);
+
+    let data = std::fs::read("tests/squid/squid.conf").unwrap();
+    let text = std::str::from_utf8(&data[cond.span.range.clone()]).unwrap();
+    assert!(text.starts_with("if ${process_number}"));
+    assert!(text.ends_with("endif"));
+}
+
+#[test]
+fn include() {
+    let mut conf = parse("tests/squid/squid.conf");
+    conf.resolve_include(None, None).unwrap();
+    let refresh = conf.root.query("refresh_pattern");
+    assert_eq!(refresh.len(), 2);
+    assert_eq!(
+        refresh[1].span.to_string(),
+        "tests/squid/conf.d/refresh.conf:2:1"
+    );
+}
+
+#[test]
+fn errors() {
+    use misc_conf::error::{ErrorKind, ParseError};
+
+    let err = Directive::<Squid>::parse(b"if a\nhttp_port 80\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnexpectedEof);
+    assert_eq!(err.expected, ["endif"]);
+
+    let err = Directive::<Squid>::parse(b"http_port 80\nendif\n").unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.kind, ErrorKind::UnexpectedToken);
+    assert_eq!(err.position.line, 2);
+}
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::render::Render;
+
+    let conf = parse("tests/squid/squid.conf");
+    let text = conf.render();
+    let reparsed = Directive::<Squid>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf.root_directives(), "{text}");
+    assert_eq!(reparsed.render(), text);
+    assert!(text.contains(r#"[%tl] "%rm %ru HTTP/%rv" %>Hs"#), "{text}");
+}
+
+#[test]
+fn render_as_stored() {
+    use misc_conf::render::Render;
+
+    let input = b"acl bad url_regex -i don't a#b\nacl q dstdomain \"a b\" 'c d'\n";
+    let conf = Directive::<Squid>::parse(input).unwrap();
+    assert_eq!(conf[0].args, ["bad", "url_regex", "-i", "don't", "a#b"]);
+    assert_eq!(conf[1].args, ["q", "dstdomain", "\"a b\"", "'c", "d'"]);
+
+    let text = conf.render();
+    assert_eq!(text.as_bytes(), input);
+    assert_eq!(Directive::<Squid>::parse(text.as_bytes()).unwrap(), conf);
+}
+
+#[test]
+fn escaped_quote() {
+    let conf = Directive::<Squid>::parse(b"logformat x \"a\\\" b\" c\n").unwrap();
+    assert_eq!(conf[0].args, ["x", "\"a\\\" b\"", "c"]);
+}
@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use misc_conf::ast::*;
+use misc_conf::cpath::CPathBuf;
+use misc_conf::ssh::Ssh;
+
+fn parse(path: impl AsRef<Path>) -> Config<Ssh> {
+    let path = path.as_ref();
+    println!("parsing: {:?}", path);
+    Config::<Ssh>::parse(path.to_path_buf()).unwrap()
+}
+
+#[test]
+fn directives() {
+    let conf = parse("tests/ssh/sshd_config");
+    let root = conf.root_directives();
+    assert_eq!(root.len(), 10);
+
+    let password = conf.root.query("PasswordAuthentication").pop().unwrap();
+    assert_eq!(password.args, ["no"]);
+    assert_eq!(password.span.args[0].line, 7);
+    assert_eq!(password.span.args[0].column, 24);
+
+    let keys = conf.root.query("AuthorizedKeysFile").pop().unwrap();
+    assert_eq!(keys.args, [".ssh/authorized_keys", "/etc/ssh/keys dir/%u"]);
+}
+
+#[test]
+fn sections() {
+    let conf = parse("tests/ssh/sshd_config");
+    let sections = conf.root.query("match");
+    assert_eq!(sections.len(), 2);
+    assert_eq!(sections[0].args, ["Group", "sftp-only"]);
+    let names = sections[0]
+        .children
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["ChrootDirectory", "ForceCommand", "AllowTcpForwarding"]
+    );
+
+    let root_login = conf.root.query("match/PermitRootLogin").pop().unwrap();
+    assert_eq!(root_login.args, ["prohibit-password"]);
+
+    let cp = CPathBuf::parse("(?i)match[1='admin']/PasswordAuthentication[0='yes']").unwrap();
+    assert_eq!(conf.root.cpath_query(&cp).len(), 1);
+
+    let data = std::fs::read("tests/ssh/sshd_config").unwrap();
+    let text = std::str::from_utf8(&data[sections[0].span.range.clone()]).unwrap();
+    assert!(text.starts_with("Match Group"));
+    assert!(text.ends_with("AllowTcpForwarding no"));
+}
+
+#[test]
+fn include() {
+    let mut conf = parse("tests/ssh/sshd_config");
+    conf.resolve_include(None, None).unwrap();
+    let root = conf.root_directives();
+    assert_eq!(root[0].name, "KexAlgorithms");
+    let macs = conf.root.query("MACs").pop().unwrap();
+    assert_eq!(
+        macs.span.to_string(),
+        "tests/ssh/sshd_config.d/10-crypto.conf:2:1"
+    );
+}
+
+#[test]
+fn render_roundtrip() {
+    use misc_conf::render::Render;
+
+    let conf = parse("tests/ssh/sshd_config");
+    let text = conf.render();
+    let reparsed = Directive::<Ssh>::parse(text.as_bytes()).unwrap();
+    assert_eq!(reparsed, conf.root_directives(), "{text}");
+    assert_eq!(reparsed.render(), text);
+}
+
+#[test]
+fn quoted_name() {
+    use misc_conf::render::Render;
+
+    let conf = vec![Directive::<Ssh>::new("'", ["a\\b"])];
+    let text = conf.render();
+    assert_eq!(
+        Directive::<Ssh>::parse(text.as_bytes()).unwrap(),
+        conf,
+        "{text}"
+    );
+}